@@ -0,0 +1,78 @@
+/// The characters with names of their own, as in `#\space`.
+const CHAR_NAMES: [(&str, char); 9] = [("alarm", '\u{7}'),
+                                        ("backspace", '\u{8}'),
+                                        ("delete", '\u{7f}'),
+                                        ("escape", '\u{1b}'),
+                                        ("newline", '\n'),
+                                        ("null", '\0'),
+                                        ("return", '\r'),
+                                        ("space", ' '),
+                                        ("tab", '\t')];
+
+/// The zero of every run of decimal digits (general category Nd) in Unicode 14.
+const DIGIT_ZEROS: [u32; 66] = [0x30, 0x660, 0x6F0, 0x7C0, 0x966, 0x9E6, 0xA66, 0xAE6, 0xB66,
+                                0xBE6, 0xC66, 0xCE6, 0xD66, 0xDE6, 0xE50, 0xED0, 0xF20, 0x1040,
+                                0x1090, 0x17E0, 0x1810, 0x1946, 0x19D0, 0x1A80, 0x1A90, 0x1B50,
+                                0x1BB0, 0x1C40, 0x1C50, 0xA620, 0xA8D0, 0xA900, 0xA9D0, 0xA9F0,
+                                0xAA50, 0xABF0, 0xFF10, 0x104A0, 0x10D30, 0x11066, 0x110F0,
+                                0x11136, 0x111D0, 0x112F0, 0x11450, 0x114D0, 0x11650, 0x116C0,
+                                0x11730, 0x118E0, 0x11950, 0x11C50, 0x11D50, 0x11DA0, 0x16A60,
+                                0x16AC0, 0x16B50, 0x1D7CE, 0x1D7D8, 0x1D7E2, 0x1D7EC, 0x1D7F6,
+                                0x1E140, 0x1E2F0, 0x1E950, 0x1FBF0];
+
+/// Parses what follows `#\` in a character literal: the character itself, one of the
+/// names above or a hex scalar value such as `x41`.
+pub fn parse_char(text: &str) -> Option<char> {
+    let mut chars = text.chars();
+    let first = chars.next()?;
+    if chars.next().is_none() {
+        return Some(first);
+    }
+    if let Some(&(_, c)) = CHAR_NAMES.iter().find(|&&(name, _)| name == text) {
+        return Some(c);
+    }
+    if first == 'x' || first == 'X' {
+        return u32::from_str_radix(&text[1..], 16).ok().and_then(::std::char::from_u32);
+    }
+    None
+}
+
+/// Writes a character the way `write` does, so that it can be read back.
+pub fn write_char(c: char) -> String {
+    match CHAR_NAMES.iter().find(|&&(_, named)| named == c) {
+        Some(&(name, _)) => format!("#\\{}", name),
+        None if c.is_control() => format!("#\\x{:x}", c as u32),
+        None => format!("#\\{}", c),
+    }
+}
+
+/// The value of a decimal digit in any script, or `None` if `c` isn't one.
+pub fn digit_value(c: char) -> Option<u32> {
+    let code = c as u32;
+    DIGIT_ZEROS.iter()
+        .find(|&&zero| code >= zero && code < zero + 10)
+        .map(|zero| code - zero)
+}
+
+/// Maps a character through one of Rust's case conversions, keeping it as it is if the
+/// conversion doesn't give a single character (as for `ß`, which upcases to `SS`).
+fn convert_case<I: Iterator<Item = char>>(c: char, mut converted: I) -> char {
+    match (converted.next(), converted.next()) {
+        (Some(d), None) => d,
+        _ => c,
+    }
+}
+
+pub fn upcase(c: char) -> char {
+    convert_case(c, c.to_uppercase())
+}
+
+pub fn downcase(c: char) -> char {
+    convert_case(c, c.to_lowercase())
+}
+
+/// Folds the case of a character for the `-ci` comparisons. Going through upper case
+/// first makes variants such as the final sigma fold to the same character.
+pub fn foldcase(c: char) -> char {
+    downcase(upcase(c))
+}
@@ -0,0 +1,90 @@
+use expression::LValue;
+use program::Continuation;
+use std::fmt;
+use std::fmt::Formatter;
+use std::rc::Rc;
+
+/// What kind of error a condition describes, as told apart by `file-error?` and friends.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum ConditionKind {
+    Error,
+    File,
+    Read,
+}
+
+/// An error object, as created by `error` or by the interpreter itself.
+#[derive(Debug,Clone)]
+pub struct Condition {
+    pub kind: ConditionKind,
+    pub message: String,
+    pub irritants: Vec<LValue>,
+    /// Where in the files being loaded the condition was signalled, if anywhere.
+    pub location: Option<String>,
+}
+
+impl Condition {
+    pub fn new(message: String, irritants: Vec<LValue>) -> Condition {
+        Condition {
+            kind: ConditionKind::Error,
+            message,
+            irritants,
+            location: None,
+        }
+    }
+
+    pub fn file_error(message: String, irritants: Vec<LValue>) -> Condition {
+        Condition {
+            kind: ConditionKind::File,
+            message,
+            irritants,
+            location: None,
+        }
+    }
+
+    pub fn read_error(message: String, irritants: Vec<LValue>) -> Condition {
+        Condition {
+            kind: ConditionKind::Read,
+            message,
+            irritants,
+            location: None,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if let Some(ref location) = self.location {
+            write!(f, "{}: ", location)?;
+        }
+        write!(f, "{}", self.message)?;
+        for irritant in &self.irritants {
+            write!(f, " {}", irritant)?;
+        }
+        Ok(())
+    }
+}
+
+/// A named point of recovery established by `restart-case` or by the REPL.
+#[derive(Debug,Clone)]
+pub struct Restart {
+    pub id: usize,
+    pub name: String,
+    pub description: String,
+    pub arity: usize,
+}
+
+/// The reasons why the evaluation of an expression may stop without producing a value.
+#[derive(Debug,Clone)]
+pub enum Unwind {
+    /// An error detected by the interpreter that hasn't been signalled to the handlers yet.
+    Error(String),
+    /// Like `Error`, for errors that need a condition of their own kind or irritants.
+    Signal(Condition),
+    /// An object that was raised and left unhandled, on its way to the top level.
+    Raise(LValue),
+    /// A transfer of control to the restart (or other exit point) with the given id.
+    Escape(usize, Vec<LValue>),
+    /// A transfer of control to a continuation captured by a run of the evaluator further
+    /// out, which has to be left before the continuation can be reinstated.
+    Reenter(Rc<Continuation>, Vec<LValue>),
+}
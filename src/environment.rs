@@ -0,0 +1,48 @@
+use expression::LValue;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Formatter;
+use std::rc::Rc;
+
+/// A set of bindings, together with the environment it extends.
+pub struct Frame {
+    pub bindings: HashMap<String, LValue>,
+    pub parent: Option<Environment>,
+}
+
+pub type Environment = Rc<RefCell<Frame>>;
+
+impl Frame {
+    pub fn new(bindings: HashMap<String, LValue>, parent: Option<Environment>) -> Environment {
+        Rc::new(RefCell::new(Frame {
+            bindings,
+            parent,
+        }))
+    }
+
+    /// Finds the value bound to `name`, looking through the enclosing environments.
+    pub fn lookup(env: &Environment, name: &str) -> Option<LValue> {
+        let mut current = env.clone();
+        loop {
+            let parent = {
+                let frame = current.borrow();
+                if let Some(value) = frame.bindings.get(name) {
+                    return Some(value.clone());
+                }
+                frame.parent.clone()
+            };
+            match parent {
+                Some(p) => current = p,
+                None => return None,
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Frame {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // Frames usually contain closures that refer back to them, so don't descend.
+        write!(f, "Frame({} bindings)", self.bindings.len())
+    }
+}
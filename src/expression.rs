@@ -1,14 +1,27 @@
 
 use list::*;
+use condition::*;
+use environment::Environment;
+use port::Port;
+use library::*;
+use character;
+use numeric_vector::*;
+use hash_table::HashTable;
+use program::Continuation;
 use std::fmt;
 use std::fmt::Formatter;
-use std::cmp::Ordering;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::ops::Deref;
+use std::path::Path;
+use std::rc::Rc;
 
 #[derive(Debug,Clone)]
 pub enum Procedure {
     UserDefined {
-        arguments: Vec<String>,
-        body: Vec<Expression>,
+        lambda: Rc<Lambda>,
+        environment: Environment,
     },
     Sum,
     Difference,
@@ -17,86 +30,1390 @@ pub enum Procedure {
     Equal,
     Less,
     Greater,
+    LessEqual,
+    GreaterEqual,
+    StringEqual,
+    StringLess,
+    StringGreater,
+    StringLessEqual,
+    StringGreaterEqual,
+    BooleanEqual,
     And,
     Or,
     Not,
+    Cons,
+    Car,
+    Cdr,
+    List,
+    WithExceptionHandler,
+    Raise,
+    RaiseContinuable,
+    Error,
+    IsErrorObject,
+    ErrorObjectMessage,
+    ErrorObjectIrritants,
+    ComputeRestarts,
+    FindRestart,
+    InvokeRestart,
+    RestartName,
+    DynamicWind,
+    CallWithCurrentContinuation,
+    Continuation(Rc<Continuation>),
+    Values,
+    CallWithValues,
+    Force,
+    MakePromise,
+    IsPromise,
+    IsNull,
+    IsPair,
+    MakeParameter,
+    Parameter(Rc<Parameter>),
+    Display,
+    Write,
+    WriteString,
+    WriteChar,
+    Newline,
+    FlushOutputPort,
+    OpenInputFile,
+    OpenOutputFile,
+    CallWithInputFile,
+    CallWithOutputFile,
+    WithOutputToFile,
+    ReadLine,
+    ReadChar,
+    PeekChar,
+    ReadString,
+    IsCharReady,
+    EofObject,
+    IsEofObject,
+    ClosePort,
+    IsFileError,
+    OpenInputString,
+    OpenOutputString,
+    GetOutputString,
+    WithOutputToString,
+    Read,
+    IsReadError,
+    Eval,
+    SchemeReportEnvironment,
+    NullEnvironment,
+    InteractionEnvironment,
+    Environment,
+    TheEnvironment,
+    IsEnvironmentBound,
+    EnvironmentBoundNames,
+    EnvironmentLookup,
+    EnvironmentDefine,
+    EnvironmentParent,
+    Load,
+    Features,
+    SetCar,
+    SetCdr,
+    IsEq,
+    IsEqv,
+    IsEqual,
+    IsNumber,
+    IsComplex,
+    IsReal,
+    IsRational,
+    IsInteger,
+    IsString,
+    IsBoolean,
+    IsSymbol,
+    IsApplicable,
+    IsList,
+    IsPort,
+    IsInputPort,
+    IsOutputPort,
+    IsTextualPort,
+    IsEnvironment,
+    IsRestart,
+    TypeOf,
+    IsChar,
+    CharToInteger,
+    IntegerToChar,
+    CharEqual,
+    CharLess,
+    CharGreater,
+    CharLessEqual,
+    CharGreaterEqual,
+    CharEqualCi,
+    CharLessCi,
+    CharGreaterCi,
+    CharLessEqualCi,
+    CharGreaterEqualCi,
+    IsCharAlphabetic,
+    IsCharNumeric,
+    IsCharWhitespace,
+    IsCharUpperCase,
+    IsCharLowerCase,
+    DigitValue,
+    CharUpcase,
+    CharDowncase,
+    CharFoldcase,
+    MakeString,
+    StringFromChars,
+    StringLength,
+    StringRef,
+    Substring,
+    StringAppend,
+    StringCopy,
+    StringSet,
+    StringFill,
+    StringToList,
+    ListToString,
+    StringUpcase,
+    StringDowncase,
+    StringFoldcase,
+    StringMap,
+    StringForEach,
+    StringIndex,
+    StringSearchForward,
+    StringSplit,
+    StringJoin,
+    StringTrim,
+    StringTrimRight,
+    StringTrimBoth,
+    StringPad,
+    StringPadRight,
+    IsVector,
+    MakeVector,
+    Vector,
+    VectorLength,
+    VectorRef,
+    VectorSet,
+    VectorToList,
+    ListToVector,
+    VectorFill,
+    VectorCopy,
+    VectorCopyInto,
+    VectorAppend,
+    VectorMap,
+    VectorForEach,
+    IsBytevector,
+    MakeBytevector,
+    Bytevector,
+    BytevectorLength,
+    BytevectorU8Ref,
+    BytevectorU8Set,
+    BytevectorCopy,
+    BytevectorCopyInto,
+    BytevectorAppend,
+    Utf8ToString,
+    StringToUtf8,
+    IsBinaryPort,
+    OpenInputBytevector,
+    OpenOutputBytevector,
+    GetOutputBytevector,
+    OpenBinaryInputFile,
+    OpenBinaryOutputFile,
+    ReadU8,
+    PeekU8,
+    IsU8Ready,
+    ReadBytevector,
+    WriteU8,
+    WriteBytevector,
+    /// One of the procedures on the homogeneous vectors of an element type, as in
+    /// `f64vector-ref`.
+    NumericVector(ElementType, NumericVectorOp),
+    IsHashTable,
+    MakeHashTable,
+    HashTableRef,
+    HashTableRefDefault,
+    HashTableSet,
+    HashTableDelete,
+    HashTableContains,
+    HashTableUpdate,
+    HashTableUpdateDefault,
+    HashTableSize,
+    HashTableKeys,
+    HashTableValues,
+    HashTableWalk,
+    HashTableToAlist,
+    Hash,
+    StringHash,
 }
 
-impl fmt::Display for Procedure {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+impl Procedure {
+    /// Every builtin procedure, as bound in the global environment.
+    pub fn builtins() -> Vec<Procedure> {
+        let mut builtins = vec![Procedure::Sum,
+                                Procedure::Difference,
+                                Procedure::Product,
+                                Procedure::Division,
+                                Procedure::Equal,
+                                Procedure::Less,
+                                Procedure::Greater,
+                                Procedure::LessEqual,
+                                Procedure::GreaterEqual,
+                                Procedure::StringEqual,
+                                Procedure::StringLess,
+                                Procedure::StringGreater,
+                                Procedure::StringLessEqual,
+                                Procedure::StringGreaterEqual,
+                                Procedure::BooleanEqual,
+                                Procedure::And,
+                                Procedure::Or,
+                                Procedure::Not,
+                                Procedure::Cons,
+                                Procedure::Car,
+                                Procedure::Cdr,
+                                Procedure::List,
+                                Procedure::WithExceptionHandler,
+                                Procedure::Raise,
+                                Procedure::RaiseContinuable,
+                                Procedure::Error,
+                                Procedure::IsErrorObject,
+                                Procedure::ErrorObjectMessage,
+                                Procedure::ErrorObjectIrritants,
+                                Procedure::ComputeRestarts,
+                                Procedure::FindRestart,
+                                Procedure::InvokeRestart,
+                                Procedure::RestartName,
+                                Procedure::DynamicWind,
+                                Procedure::CallWithCurrentContinuation,
+                                Procedure::Values,
+                                Procedure::CallWithValues,
+                                Procedure::Force,
+                                Procedure::MakePromise,
+                                Procedure::IsPromise,
+                                Procedure::IsNull,
+                                Procedure::IsPair,
+                                Procedure::MakeParameter,
+                                Procedure::Display,
+                                Procedure::Write,
+                                Procedure::WriteString,
+                                Procedure::WriteChar,
+                                Procedure::Newline,
+                                Procedure::FlushOutputPort,
+                                Procedure::OpenInputFile,
+                                Procedure::OpenOutputFile,
+                                Procedure::CallWithInputFile,
+                                Procedure::CallWithOutputFile,
+                                Procedure::WithOutputToFile,
+                                Procedure::ReadLine,
+                                Procedure::ReadChar,
+                                Procedure::PeekChar,
+                                Procedure::ReadString,
+                                Procedure::IsCharReady,
+                                Procedure::EofObject,
+                                Procedure::IsEofObject,
+                                Procedure::ClosePort,
+                                Procedure::IsFileError,
+                                Procedure::OpenInputString,
+                                Procedure::OpenOutputString,
+                                Procedure::GetOutputString,
+                                Procedure::WithOutputToString,
+                                Procedure::Read,
+                                Procedure::IsReadError,
+                                Procedure::Eval,
+                                Procedure::SchemeReportEnvironment,
+                                Procedure::NullEnvironment,
+                                Procedure::InteractionEnvironment,
+                                Procedure::Environment,
+                                Procedure::TheEnvironment,
+                                Procedure::IsEnvironmentBound,
+                                Procedure::EnvironmentBoundNames,
+                                Procedure::EnvironmentLookup,
+                                Procedure::EnvironmentDefine,
+                                Procedure::EnvironmentParent,
+                                Procedure::Load,
+                                Procedure::Features,
+                                Procedure::SetCar,
+                                Procedure::SetCdr,
+                                Procedure::IsEq,
+                                Procedure::IsEqv,
+                                Procedure::IsEqual,
+                                Procedure::IsNumber,
+                                Procedure::IsComplex,
+                                Procedure::IsReal,
+                                Procedure::IsRational,
+                                Procedure::IsInteger,
+                                Procedure::IsString,
+                                Procedure::IsBoolean,
+                                Procedure::IsSymbol,
+                                Procedure::IsApplicable,
+                                Procedure::IsList,
+                                Procedure::IsPort,
+                                Procedure::IsInputPort,
+                                Procedure::IsOutputPort,
+                                Procedure::IsTextualPort,
+                                Procedure::IsEnvironment,
+                                Procedure::IsRestart,
+                                Procedure::TypeOf,
+                                Procedure::IsChar,
+                                Procedure::CharToInteger,
+                                Procedure::IntegerToChar,
+                                Procedure::CharEqual,
+                                Procedure::CharLess,
+                                Procedure::CharGreater,
+                                Procedure::CharLessEqual,
+                                Procedure::CharGreaterEqual,
+                                Procedure::CharEqualCi,
+                                Procedure::CharLessCi,
+                                Procedure::CharGreaterCi,
+                                Procedure::CharLessEqualCi,
+                                Procedure::CharGreaterEqualCi,
+                                Procedure::IsCharAlphabetic,
+                                Procedure::IsCharNumeric,
+                                Procedure::IsCharWhitespace,
+                                Procedure::IsCharUpperCase,
+                                Procedure::IsCharLowerCase,
+                                Procedure::DigitValue,
+                                Procedure::CharUpcase,
+                                Procedure::CharDowncase,
+                                Procedure::CharFoldcase,
+                                Procedure::MakeString,
+                                Procedure::StringFromChars,
+                                Procedure::StringLength,
+                                Procedure::StringRef,
+                                Procedure::Substring,
+                                Procedure::StringAppend,
+                                Procedure::StringCopy,
+                                Procedure::StringSet,
+                                Procedure::StringFill,
+                                Procedure::StringToList,
+                                Procedure::ListToString,
+                                Procedure::StringUpcase,
+                                Procedure::StringDowncase,
+                                Procedure::StringFoldcase,
+                                Procedure::StringMap,
+                                Procedure::StringForEach,
+                                Procedure::StringIndex,
+                                Procedure::StringSearchForward,
+                                Procedure::StringSplit,
+                                Procedure::StringJoin,
+                                Procedure::StringTrim,
+                                Procedure::StringTrimRight,
+                                Procedure::StringTrimBoth,
+                                Procedure::StringPad,
+                                Procedure::StringPadRight,
+                                Procedure::IsVector,
+                                Procedure::MakeVector,
+                                Procedure::Vector,
+                                Procedure::VectorLength,
+                                Procedure::VectorRef,
+                                Procedure::VectorSet,
+                                Procedure::VectorToList,
+                                Procedure::ListToVector,
+                                Procedure::VectorFill,
+                                Procedure::VectorCopy,
+                                Procedure::VectorCopyInto,
+                                Procedure::VectorAppend,
+                                Procedure::VectorMap,
+                                Procedure::VectorForEach,
+                                Procedure::IsBytevector,
+                                Procedure::MakeBytevector,
+                                Procedure::Bytevector,
+                                Procedure::BytevectorLength,
+                                Procedure::BytevectorU8Ref,
+                                Procedure::BytevectorU8Set,
+                                Procedure::BytevectorCopy,
+                                Procedure::BytevectorCopyInto,
+                                Procedure::BytevectorAppend,
+                                Procedure::Utf8ToString,
+                                Procedure::StringToUtf8,
+                                Procedure::IsBinaryPort,
+                                Procedure::OpenInputBytevector,
+                                Procedure::OpenOutputBytevector,
+                                Procedure::GetOutputBytevector,
+                                Procedure::OpenBinaryInputFile,
+                                Procedure::OpenBinaryOutputFile,
+                                Procedure::ReadU8,
+                                Procedure::PeekU8,
+                                Procedure::IsU8Ready,
+                                Procedure::ReadBytevector,
+                                Procedure::WriteU8,
+                                Procedure::WriteBytevector,
+                                Procedure::IsHashTable,
+                                Procedure::MakeHashTable,
+                                Procedure::HashTableRef,
+                                Procedure::HashTableRefDefault,
+                                Procedure::HashTableSet,
+                                Procedure::HashTableDelete,
+                                Procedure::HashTableContains,
+                                Procedure::HashTableUpdate,
+                                Procedure::HashTableUpdateDefault,
+                                Procedure::HashTableSize,
+                                Procedure::HashTableKeys,
+                                Procedure::HashTableValues,
+                                Procedure::HashTableWalk,
+                                Procedure::HashTableToAlist,
+                                Procedure::Hash,
+                                Procedure::StringHash];
+        for &t in &ElementType::ALL {
+            for &op in &NumericVectorOp::ALL {
+                builtins.push(Procedure::NumericVector(t, op));
+            }
+        }
+        builtins
+    }
+
+    pub fn name(&self) -> &'static str {
         match *self {
-            Procedure::UserDefined { arguments: _, body: _ } => {
-                write!(f, "#<procedure>:user-defined")
+            Procedure::UserDefined { .. } => "user-defined",
+            Procedure::Sum => "+",
+            Procedure::Difference => "-",
+            Procedure::Product => "*",
+            Procedure::Division => "/",
+            Procedure::Equal => "=",
+            Procedure::Less => "<",
+            Procedure::Greater => ">",
+            Procedure::LessEqual => "<=",
+            Procedure::GreaterEqual => ">=",
+            Procedure::StringEqual => "string=?",
+            Procedure::StringLess => "string<?",
+            Procedure::StringGreater => "string>?",
+            Procedure::StringLessEqual => "string<=?",
+            Procedure::StringGreaterEqual => "string>=?",
+            Procedure::BooleanEqual => "boolean=?",
+            Procedure::And => "and",
+            Procedure::Or => "or",
+            Procedure::Not => "not",
+            Procedure::Cons => "cons",
+            Procedure::Car => "car",
+            Procedure::Cdr => "cdr",
+            Procedure::List => "list",
+            Procedure::WithExceptionHandler => "with-exception-handler",
+            Procedure::Raise => "raise",
+            Procedure::RaiseContinuable => "raise-continuable",
+            Procedure::Error => "error",
+            Procedure::IsErrorObject => "error-object?",
+            Procedure::ErrorObjectMessage => "error-object-message",
+            Procedure::ErrorObjectIrritants => "error-object-irritants",
+            Procedure::ComputeRestarts => "compute-restarts",
+            Procedure::FindRestart => "find-restart",
+            Procedure::InvokeRestart => "invoke-restart",
+            Procedure::RestartName => "restart-name",
+            Procedure::DynamicWind => "dynamic-wind",
+            Procedure::CallWithCurrentContinuation => "call-with-current-continuation",
+            Procedure::Continuation(_) => "continuation",
+            Procedure::Values => "values",
+            Procedure::CallWithValues => "call-with-values",
+            Procedure::Force => "force",
+            Procedure::MakePromise => "make-promise",
+            Procedure::IsPromise => "promise?",
+            Procedure::IsNull => "null?",
+            Procedure::IsPair => "pair?",
+            Procedure::MakeParameter => "make-parameter",
+            Procedure::Parameter(_) => "parameter",
+            Procedure::Display => "display",
+            Procedure::Write => "write",
+            Procedure::WriteString => "write-string",
+            Procedure::WriteChar => "write-char",
+            Procedure::Newline => "newline",
+            Procedure::FlushOutputPort => "flush-output-port",
+            Procedure::OpenInputFile => "open-input-file",
+            Procedure::OpenOutputFile => "open-output-file",
+            Procedure::CallWithInputFile => "call-with-input-file",
+            Procedure::CallWithOutputFile => "call-with-output-file",
+            Procedure::WithOutputToFile => "with-output-to-file",
+            Procedure::ReadLine => "read-line",
+            Procedure::ReadChar => "read-char",
+            Procedure::PeekChar => "peek-char",
+            Procedure::ReadString => "read-string",
+            Procedure::IsCharReady => "char-ready?",
+            Procedure::EofObject => "eof-object",
+            Procedure::IsEofObject => "eof-object?",
+            Procedure::ClosePort => "close-port",
+            Procedure::IsFileError => "file-error?",
+            Procedure::OpenInputString => "open-input-string",
+            Procedure::OpenOutputString => "open-output-string",
+            Procedure::GetOutputString => "get-output-string",
+            Procedure::WithOutputToString => "with-output-to-string",
+            Procedure::Read => "read",
+            Procedure::IsReadError => "read-error?",
+            Procedure::Eval => "eval",
+            Procedure::SchemeReportEnvironment => "scheme-report-environment",
+            Procedure::NullEnvironment => "null-environment",
+            Procedure::InteractionEnvironment => "interaction-environment",
+            Procedure::Environment => "environment",
+            Procedure::TheEnvironment => "the-environment",
+            Procedure::IsEnvironmentBound => "environment-bound?",
+            Procedure::EnvironmentBoundNames => "environment-bound-names",
+            Procedure::EnvironmentLookup => "environment-lookup",
+            Procedure::EnvironmentDefine => "environment-define",
+            Procedure::EnvironmentParent => "environment-parent",
+            Procedure::Load => "load",
+            Procedure::Features => "features",
+            Procedure::SetCar => "set-car!",
+            Procedure::SetCdr => "set-cdr!",
+            Procedure::IsEq => "eq?",
+            Procedure::IsEqv => "eqv?",
+            Procedure::IsEqual => "equal?",
+            Procedure::IsNumber => "number?",
+            Procedure::IsComplex => "complex?",
+            Procedure::IsReal => "real?",
+            Procedure::IsRational => "rational?",
+            Procedure::IsInteger => "integer?",
+            Procedure::IsString => "string?",
+            Procedure::IsBoolean => "boolean?",
+            Procedure::IsSymbol => "symbol?",
+            Procedure::IsApplicable => "procedure?",
+            Procedure::IsList => "list?",
+            Procedure::IsPort => "port?",
+            Procedure::IsInputPort => "input-port?",
+            Procedure::IsOutputPort => "output-port?",
+            Procedure::IsTextualPort => "textual-port?",
+            Procedure::IsEnvironment => "environment?",
+            Procedure::IsRestart => "restart?",
+            Procedure::TypeOf => "type-of",
+            Procedure::IsChar => "char?",
+            Procedure::CharToInteger => "char->integer",
+            Procedure::IntegerToChar => "integer->char",
+            Procedure::CharEqual => "char=?",
+            Procedure::CharLess => "char<?",
+            Procedure::CharGreater => "char>?",
+            Procedure::CharLessEqual => "char<=?",
+            Procedure::CharGreaterEqual => "char>=?",
+            Procedure::CharEqualCi => "char-ci=?",
+            Procedure::CharLessCi => "char-ci<?",
+            Procedure::CharGreaterCi => "char-ci>?",
+            Procedure::CharLessEqualCi => "char-ci<=?",
+            Procedure::CharGreaterEqualCi => "char-ci>=?",
+            Procedure::IsCharAlphabetic => "char-alphabetic?",
+            Procedure::IsCharNumeric => "char-numeric?",
+            Procedure::IsCharWhitespace => "char-whitespace?",
+            Procedure::IsCharUpperCase => "char-upper-case?",
+            Procedure::IsCharLowerCase => "char-lower-case?",
+            Procedure::DigitValue => "digit-value",
+            Procedure::CharUpcase => "char-upcase",
+            Procedure::CharDowncase => "char-downcase",
+            Procedure::CharFoldcase => "char-foldcase",
+            Procedure::MakeString => "make-string",
+            Procedure::StringFromChars => "string",
+            Procedure::StringLength => "string-length",
+            Procedure::StringRef => "string-ref",
+            Procedure::Substring => "substring",
+            Procedure::StringAppend => "string-append",
+            Procedure::StringCopy => "string-copy",
+            Procedure::StringSet => "string-set!",
+            Procedure::StringFill => "string-fill!",
+            Procedure::StringToList => "string->list",
+            Procedure::ListToString => "list->string",
+            Procedure::StringUpcase => "string-upcase",
+            Procedure::StringDowncase => "string-downcase",
+            Procedure::StringFoldcase => "string-foldcase",
+            Procedure::StringMap => "string-map",
+            Procedure::StringForEach => "string-for-each",
+            Procedure::StringIndex => "string-index",
+            Procedure::StringSearchForward => "string-search-forward",
+            Procedure::StringSplit => "string-split",
+            Procedure::StringJoin => "string-join",
+            Procedure::StringTrim => "string-trim",
+            Procedure::StringTrimRight => "string-trim-right",
+            Procedure::StringTrimBoth => "string-trim-both",
+            Procedure::StringPad => "string-pad",
+            Procedure::StringPadRight => "string-pad-right",
+            Procedure::IsVector => "vector?",
+            Procedure::MakeVector => "make-vector",
+            Procedure::Vector => "vector",
+            Procedure::VectorLength => "vector-length",
+            Procedure::VectorRef => "vector-ref",
+            Procedure::VectorSet => "vector-set!",
+            Procedure::VectorToList => "vector->list",
+            Procedure::ListToVector => "list->vector",
+            Procedure::VectorFill => "vector-fill!",
+            Procedure::VectorCopy => "vector-copy",
+            Procedure::VectorCopyInto => "vector-copy!",
+            Procedure::VectorAppend => "vector-append",
+            Procedure::VectorMap => "vector-map",
+            Procedure::VectorForEach => "vector-for-each",
+            Procedure::IsBytevector => "bytevector?",
+            Procedure::MakeBytevector => "make-bytevector",
+            Procedure::Bytevector => "bytevector",
+            Procedure::BytevectorLength => "bytevector-length",
+            Procedure::BytevectorU8Ref => "bytevector-u8-ref",
+            Procedure::BytevectorU8Set => "bytevector-u8-set!",
+            Procedure::BytevectorCopy => "bytevector-copy",
+            Procedure::BytevectorCopyInto => "bytevector-copy!",
+            Procedure::BytevectorAppend => "bytevector-append",
+            Procedure::Utf8ToString => "utf8->string",
+            Procedure::StringToUtf8 => "string->utf8",
+            Procedure::IsBinaryPort => "binary-port?",
+            Procedure::OpenInputBytevector => "open-input-bytevector",
+            Procedure::OpenOutputBytevector => "open-output-bytevector",
+            Procedure::GetOutputBytevector => "get-output-bytevector",
+            Procedure::OpenBinaryInputFile => "open-binary-input-file",
+            Procedure::OpenBinaryOutputFile => "open-binary-output-file",
+            Procedure::ReadU8 => "read-u8",
+            Procedure::PeekU8 => "peek-u8",
+            Procedure::IsU8Ready => "u8-ready?",
+            Procedure::ReadBytevector => "read-bytevector",
+            Procedure::WriteU8 => "write-u8",
+            Procedure::WriteBytevector => "write-bytevector",
+            Procedure::NumericVector(t, op) => procedure_name(t, op),
+            Procedure::IsHashTable => "hash-table?",
+            Procedure::MakeHashTable => "make-hash-table",
+            Procedure::HashTableRef => "hash-table-ref",
+            Procedure::HashTableRefDefault => "hash-table-ref/default",
+            Procedure::HashTableSet => "hash-table-set!",
+            Procedure::HashTableDelete => "hash-table-delete!",
+            Procedure::HashTableContains => "hash-table-contains?",
+            Procedure::HashTableUpdate => "hash-table-update!",
+            Procedure::HashTableUpdateDefault => "hash-table-update!/default",
+            Procedure::HashTableSize => "hash-table-size",
+            Procedure::HashTableKeys => "hash-table-keys",
+            Procedure::HashTableValues => "hash-table-values",
+            Procedure::HashTableWalk => "hash-table-walk",
+            Procedure::HashTableToAlist => "hash-table->alist",
+            Procedure::Hash => "hash",
+            Procedure::StringHash => "string-hash",
+        }
+    }
+
+    /// The least and, unless there's no limit, the greatest number of arguments a builtin
+    /// accepts. Procedures that aren't listed take a single argument.
+    pub fn arity(&self) -> (usize, Option<usize>) {
+        match *self {
+            Procedure::UserDefined { .. } | Procedure::Continuation(_) | Procedure::Sum |
+            Procedure::Product | Procedure::List | Procedure::Values | Procedure::Environment => {
+                (0, None)
+            }
+            Procedure::Difference | Procedure::Division | Procedure::Error |
+            Procedure::InvokeRestart | Procedure::Equal | Procedure::Less |
+            Procedure::Greater | Procedure::LessEqual | Procedure::GreaterEqual |
+            Procedure::StringEqual | Procedure::StringLess | Procedure::StringGreater |
+            Procedure::StringLessEqual | Procedure::StringGreaterEqual |
+            Procedure::BooleanEqual | Procedure::CharEqual | Procedure::CharLess |
+            Procedure::CharGreater | Procedure::CharLessEqual | Procedure::CharGreaterEqual |
+            Procedure::CharEqualCi | Procedure::CharLessCi | Procedure::CharGreaterCi |
+            Procedure::CharLessEqualCi | Procedure::CharGreaterEqualCi => (1, None),
+            Procedure::StringFromChars | Procedure::StringAppend | Procedure::Vector |
+            Procedure::VectorAppend | Procedure::Bytevector | Procedure::BytevectorAppend => {
+                (0, None)
+            }
+            Procedure::StringMap | Procedure::StringForEach | Procedure::VectorMap |
+            Procedure::VectorForEach => (2, None),
+            Procedure::MakeString | Procedure::StringJoin | Procedure::MakeVector |
+            Procedure::MakeBytevector | Procedure::ReadBytevector | Procedure::WriteU8 => {
+                (1, Some(2))
+            }
+            Procedure::StringCopy | Procedure::StringToList | Procedure::VectorToList |
+            Procedure::VectorCopy | Procedure::BytevectorCopy | Procedure::Utf8ToString |
+            Procedure::StringToUtf8 => (1, Some(3)),
+            Procedure::WriteBytevector => (1, Some(4)),
+            Procedure::MakeHashTable => (0, Some(1)),
+            Procedure::HashTableRef => (2, Some(4)),
+            Procedure::HashTableRefDefault | Procedure::HashTableSet => (3, Some(3)),
+            Procedure::HashTableDelete => (2, None),
+            Procedure::HashTableContains | Procedure::HashTableWalk => (2, Some(2)),
+            Procedure::HashTableUpdate => (3, Some(5)),
+            Procedure::HashTableUpdateDefault => (4, Some(4)),
+            Procedure::Hash | Procedure::StringHash => (1, Some(2)),
+            Procedure::NumericVector(_, op) => {
+                match op {
+                    NumericVectorOp::Make => (1, Some(2)),
+                    NumericVectorOp::Construct => (0, None),
+                    NumericVectorOp::Ref => (2, Some(2)),
+                    NumericVectorOp::Set => (3, Some(3)),
+                    _ => (1, Some(1)),
+                }
+            }
+            Procedure::Substring | Procedure::StringPad | Procedure::StringPadRight => (2, Some(3)),
+            Procedure::StringFill | Procedure::StringIndex | Procedure::VectorFill => (2, Some(4)),
+            Procedure::StringRef | Procedure::StringSplit | Procedure::VectorRef |
+            Procedure::BytevectorU8Ref => (2, Some(2)),
+            Procedure::StringSet | Procedure::StringSearchForward | Procedure::VectorSet |
+            Procedure::BytevectorU8Set => (3, Some(3)),
+            Procedure::VectorCopyInto | Procedure::BytevectorCopyInto => (3, Some(5)),
+            Procedure::And | Procedure::Or => (2, None),
+            Procedure::ComputeRestarts | Procedure::Parameter(_) | Procedure::EofObject |
+            Procedure::OpenOutputString | Procedure::OpenOutputBytevector |
+            Procedure::InteractionEnvironment | Procedure::TheEnvironment |
+            Procedure::Features => (0, Some(0)),
+            Procedure::Newline | Procedure::FlushOutputPort | Procedure::ReadLine |
+            Procedure::ReadChar | Procedure::PeekChar | Procedure::IsCharReady |
+            Procedure::Read | Procedure::ReadU8 | Procedure::PeekU8 | Procedure::IsU8Ready => {
+                (0, Some(1))
+            }
+            Procedure::MakeParameter | Procedure::Display | Procedure::Write |
+            Procedure::WriteChar | Procedure::ReadString | Procedure::Eval | Procedure::Load => {
+                (1, Some(2))
+            }
+            Procedure::WriteString => (1, Some(4)),
+            Procedure::Cons | Procedure::SetCar | Procedure::SetCdr | Procedure::IsEq |
+            Procedure::IsEqv | Procedure::IsEqual | Procedure::WithExceptionHandler |
+            Procedure::CallWithValues |
+            Procedure::CallWithInputFile | Procedure::CallWithOutputFile |
+            Procedure::WithOutputToFile | Procedure::IsEnvironmentBound |
+            Procedure::EnvironmentLookup => (2, Some(2)),
+            Procedure::DynamicWind | Procedure::EnvironmentDefine => (3, Some(3)),
+            _ => (1, Some(1)),
+        }
+    }
+}
+
+impl Procedure {
+    /// Whether two procedures are the same object, as far as `eqv?` can tell.
+    pub fn same(&self, other: &Procedure) -> bool {
+        match (self, other) {
+            (Procedure::UserDefined { lambda: l1, environment: e1 },
+             Procedure::UserDefined { lambda: l2, environment: e2 }) => {
+                Rc::ptr_eq(l1, l2) && Rc::ptr_eq(e1, e2)
+            }
+            (Procedure::Continuation(a), Procedure::Continuation(b)) => Rc::ptr_eq(a, b),
+            (Procedure::Parameter(a), Procedure::Parameter(b)) => Rc::ptr_eq(a, b),
+            (Procedure::NumericVector(t1, op1), Procedure::NumericVector(t2, op2)) => {
+                t1 == t2 && op1 == op2
             }
-            Procedure::Sum => write!(f, "#<procedure>:+"),
-            Procedure::Difference => write!(f, "#<procedure>:-"),
-            Procedure::Product => write!(f, "#<procedure>:*"),
-            Procedure::Division => write!(f, "#<procedure>:/"),
-            Procedure::Equal => write!(f, "#<procedure>:="),
-            Procedure::Less => write!(f, "#<procedure>:<"),
-            Procedure::Greater => write!(f, "#<procedure>:>"),
-            Procedure::And => write!(f, "#<procedure>:and"),
-            Procedure::Or => write!(f, "#<procedure>:or"),
-            Procedure::Not => write!(f, "#<procedure>:not"),
+            _ => mem::discriminant(self) == mem::discriminant(other),
+        }
+    }
+}
+
+/// The parameters and body of a `lambda` expression.
+#[derive(Debug)]
+pub struct Lambda {
+    pub arguments: Formals,
+    pub body: Body,
+}
+
+/// The parameters of a procedure or a binding form: `(a b)`, `(a b . rest)` or `rest`.
+#[derive(Debug,Clone)]
+pub struct Formals {
+    pub required: Vec<String>,
+    pub rest: Option<String>,
+}
+
+impl Formals {
+    /// Matches the given values to the parameters, returning the resulting bindings.
+    pub fn bind(&self, mut values: Vec<LValue>) -> Result<HashMap<String, LValue>, String> {
+        if values.len() < self.required.len() ||
+           (self.rest.is_none() && values.len() > self.required.len()) {
+            return Err("Invalid number of arguments provided.".to_string());
+        }
+        let rest = values.split_off(self.required.len());
+        let mut bindings: HashMap<String, LValue> = HashMap::new();
+        for (name, value) in self.required.iter().zip(values) {
+            bindings.insert(name.clone(), value);
+        }
+        if let Some(ref name) = self.rest {
+            bindings.insert(name.clone(), LValue::list(rest));
+        }
+        Ok(bindings)
+    }
+}
+
+impl fmt::Display for Procedure {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "#<procedure>:{}", self.name())
+    }
+}
+
+/// The contents of a string or of a vector of any kind, which a literal doesn't let anything
+/// modify, so that it evaluates to what the program says every time.
+#[derive(Debug)]
+pub struct Boxed<T> {
+    contents: RefCell<T>,
+    pub immutable: bool,
+}
+
+impl<T> Boxed<T> {
+    pub fn new(contents: T) -> Rc<Boxed<T>> {
+        Rc::new(Boxed {
+            contents: RefCell::new(contents),
+            immutable: false,
+        })
+    }
+
+    pub fn literal(contents: T) -> Rc<Boxed<T>> {
+        Rc::new(Boxed {
+            contents: RefCell::new(contents),
+            immutable: true,
+        })
+    }
+}
+
+impl<T> Deref for Boxed<T> {
+    type Target = RefCell<T>;
+
+    fn deref(&self) -> &RefCell<T> {
+        &self.contents
+    }
+}
+
+#[derive(Debug)]
+pub struct Pair {
+    pub car: LValue,
+    pub cdr: LValue,
+}
+
+/// A parameter object, as made by `make-parameter`. The value given here is used unless the
+/// parameter has been rebound by `parameterize`.
+#[derive(Debug)]
+pub struct Parameter {
+    pub value: LValue,
+    pub converter: Option<Procedure>,
+}
+
+#[derive(Debug,Clone)]
+pub enum PromiseState {
+    Forced(LValue),
+    Delayed {
+        body: Rc<Expression>,
+        environment: Environment,
+        /// Whether the body evaluates to another promise to be forced in turn.
+        delay_force: bool,
+    },
+}
+
+/// A promise holds its state in a shared box, so that forcing a chain of `delay-force`
+/// promises can make them all share the state of the last one.
+#[derive(Debug)]
+pub struct Promise {
+    pub state: RefCell<Rc<RefCell<PromiseState>>>,
+}
+
+impl Promise {
+    pub fn new(state: PromiseState) -> Rc<Promise> {
+        Rc::new(Promise { state: RefCell::new(Rc::new(RefCell::new(state))) })
+    }
+
+    pub fn state(&self) -> PromiseState {
+        self.state.borrow().borrow().clone()
+    }
+
+    pub fn set_state(&self, state: PromiseState) {
+        *self.state.borrow().borrow_mut() = state;
+    }
+
+    /// Takes over the state of `other`, which will share it from now on.
+    pub fn adopt(&self, other: &Promise) {
+        let own = self.state.borrow().clone();
+        let theirs = other.state.borrow().clone();
+        if !Rc::ptr_eq(&own, &theirs) {
+            let state = theirs.borrow().clone();
+            *own.borrow_mut() = state;
+            *other.state.borrow_mut() = own;
         }
     }
 }
 
 #[derive(Debug,Clone)]
 pub enum LValue {
-    StringValue(String),
+    StringValue(Rc<Boxed<String>>),
     NumericalValue(f64),
     BooleanValue(bool),
-    Quoted(Box<Expression>),
+    Character(char),
+    Symbol(String),
+    Pair(Rc<RefCell<Pair>>),
+    Nil,
+    Vector(Rc<Boxed<Vec<LValue>>>),
+    Bytevector(Rc<Boxed<Vec<u8>>>),
+    NumericVector(Rc<Boxed<NumericVector>>),
+    HashTable(Rc<RefCell<HashTable>>),
     Procedure(Procedure),
+    Condition(Rc<Condition>),
+    Restart(Restart),
+    Values(Vec<LValue>),
+    Promise(Rc<Promise>),
+    Port(Rc<RefCell<Port>>),
+    Environment(Environment),
+    Eof,
     Undefined,
 }
 
 impl fmt::Display for LValue {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.format(f, false)
+    }
+}
+
+/// Shows a value the way `display` prints it, with strings written without quotes.
+pub struct Displayed<'a>(pub &'a LValue);
+
+impl<'a> fmt::Display for Displayed<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.0.format(f, true)
+    }
+}
+
+/// The datum labels of the pairs and vectors in a value that contain themselves, so that
+/// printing a circular structure ends, as in `#0=(1 . #0#)`.
+struct DatumLabels {
+    /// The label of each node that needs one, once it has been printed.
+    labels: HashMap<usize, Option<usize>>,
+    next: usize,
+}
+
+impl DatumLabels {
+    /// Finds the nodes of `value` that can be reached again from inside themselves.
+    fn new(value: &LValue) -> DatumLabels {
+        let mut labels = HashMap::new();
+        let mut visited = HashSet::new();
+        let mut on_path = HashSet::new();
+        // The nodes from `value` down to the one being visited, with their addresses and the
+        // index of their next child to visit. It lives on the heap, as lists can be long.
+        let mut path: Vec<(LValue, usize, usize)> = Vec::new();
+        let mut next = Some(value.clone());
+        loop {
+            if let Some(node) = next.take() {
+                if let Some(address) = node.address() {
+                    if on_path.contains(&address) {
+                        labels.insert(address, None);
+                    } else if visited.insert(address) {
+                        on_path.insert(address);
+                        path.push((node, address, 0));
+                    }
+                }
+            }
+            let top = match path.last_mut() {
+                Some(top) => top,
+                None => break,
+            };
+            match top.0.child(top.2) {
+                Some(child) => {
+                    top.2 += 1;
+                    next = Some(child);
+                }
+                None => {
+                    on_path.remove(&top.1);
+                    path.pop();
+                }
+            }
+        }
+        DatumLabels { labels, next: 0 }
+    }
+
+    fn is_labeled(&self, value: &LValue) -> bool {
+        value.address().is_some_and(|address| self.labels.contains_key(&address))
+    }
+
+    /// Writes the label of `value` if it needs one. Returns whether `value` was printed
+    /// before, so that a reference to its label is all there is to write.
+    fn write_label(&mut self, f: &mut Formatter, value: &LValue) -> Result<bool, fmt::Error> {
+        let labels = &mut self.labels;
+        let label = match value.address().and_then(|address| labels.get_mut(&address)) {
+            Some(label) => label,
+            None => return Ok(false),
+        };
+        if let Some(n) = *label {
+            write!(f, "#{}#", n)?;
+            return Ok(true);
+        }
+        let n = self.next;
+        *label = Some(n);
+        self.next += 1;
+        write!(f, "#{}=", n)?;
+        Ok(false)
+    }
+}
+
+/// Escapes the characters of a string that `write` can't show as they are, so that the
+/// reader gives back the same string.
+fn escape_string(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '\u{7}' => escaped.push_str("\\a"),
+            '\u{8}' => escaped.push_str("\\b"),
+            c if c.is_control() => escaped.push_str(&format!("\\x{:x};", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl LValue {
+    /// Writes out a value, in the representation of `display` or of `write`.
+    fn format(&self, f: &mut Formatter, display: bool) -> fmt::Result {
+        self.format_labeled(f, display, &mut DatumLabels::new(self))
+    }
+
+    fn format_labeled(&self,
+                      f: &mut Formatter,
+                      display: bool,
+                      labels: &mut DatumLabels)
+                      -> fmt::Result {
+        if labels.write_label(f, self)? {
+            return Ok(());
+        }
         match *self {
-            LValue::StringValue(ref s) => write!(f, "\"{}\"", s),
+            LValue::StringValue(ref s) => {
+                if display {
+                    write!(f, "{}", s.borrow())
+                } else {
+                    write!(f, "\"{}\"", escape_string(&s.borrow()))
+                }
+            }
             LValue::NumericalValue(v) => write!(f, "{}", v),
             LValue::BooleanValue(v) => if v { write!(f, "#t") } else { write!(f, "#f") },
-            LValue::Quoted(ref e) => write!(f, "#<expression>:{:?}", e),
+            LValue::Character(c) => {
+                if display {
+                    write!(f, "{}", c)
+                } else {
+                    write!(f, "{}", character::write_char(c))
+                }
+            }
+            LValue::Symbol(ref s) => write!(f, "{}", s),
+            LValue::Pair(ref p) => {
+                write!(f, "(")?;
+                p.borrow().car.format_labeled(f, display, labels)?;
+                let mut rest = p.borrow().cdr.clone();
+                loop {
+                    rest = match rest {
+                        // A labeled pair has to start a list of its own.
+                        LValue::Pair(ref next) if !labels.is_labeled(&rest) => {
+                            write!(f, " ")?;
+                            next.borrow().car.format_labeled(f, display, labels)?;
+                            next.borrow().cdr.clone()
+                        }
+                        LValue::Nil => break,
+                        ref tail => {
+                            write!(f, " . ")?;
+                            tail.format_labeled(f, display, labels)?;
+                            break;
+                        }
+                    }
+                }
+                write!(f, ")")
+            }
+            LValue::Bytevector(ref v) => {
+                let bytes: Vec<String> = v.borrow().iter().map(|b| b.to_string()).collect();
+                write!(f, "#u8({})", bytes.join(" "))
+            }
+            LValue::NumericVector(ref v) => {
+                let v = v.borrow();
+                write!(f, "#{}({})", v.element_type().tag(), v.format_items().join(" "))
+            }
+            LValue::Nil => write!(f, "()"),
+            LValue::Vector(ref v) => {
+                write!(f, "#(")?;
+                for (i, item) in v.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    item.format_labeled(f, display, labels)?;
+                }
+                write!(f, ")")
+            }
             LValue::Procedure(ref p) => write!(f, "{}", p),
+            LValue::Condition(ref c) => write!(f, "#<condition>:{}", c),
+            LValue::Restart(ref r) => write!(f, "#<restart>:{}", r.name),
+            LValue::Values(ref values) => {
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    v.format(f, display)?;
+                }
+                Ok(())
+            }
+            LValue::HashTable(_) => write!(f, "#<hash-table>"),
+            LValue::Promise(_) => write!(f, "#<promise>"),
+            LValue::Port(_) => write!(f, "#<port>"),
+            LValue::Environment(_) => write!(f, "#<environment>"),
+            LValue::Eof => write!(f, "#<eof>"),
             LValue::Undefined => write!(f, "#<undefined>"),
         }
     }
-}
 
-impl LValue {
-    pub fn compare(&self, rhs: &LValue) -> Result<Ordering, String> {
+    /// Converts a parsed (quoted) list node to the data it denotes.
+    pub fn from_list(l: &ListNode) -> LValue {
+        LValue::from_datum(l, false)
+    }
+
+    /// Converts a quoted or self-evaluating list node in a program to the constant it
+    /// denotes, which can't be modified.
+    pub fn from_literal(l: &ListNode) -> LValue {
+        LValue::from_datum(l, true)
+    }
+
+    fn from_datum(l: &ListNode, literal: bool) -> LValue {
+        let from_datum = |l| LValue::from_datum(l, literal);
+        match *l {
+            ListNode::StringLiteral(ref s) if literal => {
+                LValue::StringValue(Boxed::literal(s.clone()))
+            }
+            ListNode::StringLiteral(ref s) => LValue::string(s.clone()),
+            ListNode::BooleanLiteral(b) => LValue::BooleanValue(b),
+            ListNode::CharLiteral(c) => LValue::Character(c),
+            ListNode::Vector(ref v) if literal => {
+                LValue::Vector(Boxed::literal(v.iter().map(from_datum).collect()))
+            }
+            ListNode::Vector(ref v) => LValue::vector(v.iter().map(from_datum).collect()),
+            ListNode::Bytevector(ref v) if literal => LValue::Bytevector(Boxed::literal(v.clone())),
+            ListNode::Bytevector(ref v) => LValue::bytevector(v.clone()),
+            ListNode::NumericVector(t, ref v) if literal => {
+                LValue::NumericVector(Boxed::literal(NumericVector::new(t, v)))
+            }
+            ListNode::NumericVector(t, ref v) => LValue::numeric_vector(t, v),
+            ListNode::NumericLiteral(v) => LValue::NumericalValue(v),
+            ListNode::Identifier(ref s) => LValue::Symbol(s.clone()),
+            ListNode::Node(ref v) => {
+                let dotted = v.len() >= 3 &&
+                             match v[v.len() - 2] {
+                    ListNode::Identifier(ref s) => s == ".",
+                    _ => false,
+                };
+                if dotted {
+                    let items = &v[..v.len() - 2];
+                    let tail = from_datum(&v[v.len() - 1]);
+                    items.iter().rev().fold(tail, |tail, item| LValue::cons(from_datum(item), tail))
+                } else {
+                    LValue::list(v.iter().map(from_datum).collect())
+                }
+            }
+        }
+    }
+
+    /// Converts data back to a list node, so that it can be evaluated as code.
+    pub fn to_list(&self) -> Result<ListNode, String> {
+        match *self {
+            LValue::StringValue(ref s) => Ok(ListNode::StringLiteral(s.borrow().clone())),
+            LValue::BooleanValue(b) => Ok(ListNode::BooleanLiteral(b)),
+            LValue::Character(c) => Ok(ListNode::CharLiteral(c)),
+            LValue::Vector(ref v) => {
+                let items = v.borrow().iter().map(LValue::to_list).collect::<Result<_, _>>()?;
+                Ok(ListNode::Vector(items))
+            }
+            LValue::Bytevector(ref v) => Ok(ListNode::Bytevector(v.borrow().clone())),
+            LValue::NumericVector(ref v) => {
+                let v = v.borrow();
+                Ok(ListNode::NumericVector(v.element_type(), v.to_f64s()))
+            }
+            LValue::NumericalValue(v) => Ok(ListNode::NumericLiteral(v)),
+            LValue::Symbol(ref s) => Ok(ListNode::Identifier(s.clone())),
+            LValue::Pair(_) | LValue::Nil => {
+                let mut children = Vec::new();
+                let mut rest = self.clone();
+                loop {
+                    rest = match rest {
+                        LValue::Pair(ref p) => {
+                            children.push(p.borrow().car.to_list()?);
+                            p.borrow().cdr.clone()
+                        }
+                        LValue::Nil => break,
+                        ref tail => {
+                            children.push(ListNode::Identifier(".".to_string()));
+                            children.push(tail.to_list()?);
+                            break;
+                        }
+                    }
+                }
+                Ok(ListNode::Node(children))
+            }
+            _ => Err(format!("Can't evaluate {} as an expression.", self)),
+        }
+    }
+
+    pub fn string(s: String) -> LValue {
+        LValue::StringValue(Boxed::new(s))
+    }
+
+    pub fn vector(items: Vec<LValue>) -> LValue {
+        LValue::Vector(Boxed::new(items))
+    }
+
+    pub fn bytevector(bytes: Vec<u8>) -> LValue {
+        LValue::Bytevector(Boxed::new(bytes))
+    }
+
+    /// Makes a homogeneous vector of type `t` out of values it accepts. The u8 vectors are
+    /// bytevectors.
+    pub fn numeric_vector(t: ElementType, values: &[f64]) -> LValue {
+        match t {
+            ElementType::U8 => LValue::bytevector(values.iter().map(|&x| x as u8).collect()),
+            _ => LValue::NumericVector(Boxed::new(NumericVector::new(t, values))),
+        }
+    }
+
+    pub fn cons(car: LValue, cdr: LValue) -> LValue {
+        LValue::Pair(Rc::new(RefCell::new(Pair { car, cdr })))
+    }
+
+    /// Packs the results of `values`. A single value stands for itself.
+    pub fn from_values(mut values: Vec<LValue>) -> LValue {
+        if values.len() == 1 {
+            values.remove(0)
+        } else {
+            LValue::Values(values)
+        }
+    }
+
+    /// Unpacks the values returned by an expression.
+    pub fn into_values(self) -> Vec<LValue> {
+        match self {
+            LValue::Values(values) => values,
+            value => vec![value],
+        }
+    }
+
+    /// Builds a proper list out of the given elements.
+    pub fn list(items: Vec<LValue>) -> LValue {
+        items.into_iter().rev().fold(LValue::Nil, |tail, item| LValue::cons(item, tail))
+    }
+
+    /// The name of the value's type, as returned by `type-of`.
+    pub fn type_name(&self) -> &'static str {
         match *self {
-            LValue::StringValue(ref s1) => {
-                match *rhs {
-                    LValue::StringValue(ref s2) => Ok(s1.cmp(s2)),
-                    _ => Err("Expected string expression as the second argument.".to_string()),
+            LValue::StringValue(_) => "string",
+            LValue::NumericalValue(_) => "number",
+            LValue::BooleanValue(_) => "boolean",
+            LValue::Character(_) => "char",
+            LValue::Symbol(_) => "symbol",
+            LValue::Pair(_) => "pair",
+            LValue::Nil => "null",
+            LValue::Vector(_) => "vector",
+            LValue::Bytevector(_) => "bytevector",
+            LValue::NumericVector(ref v) => {
+                procedure_name(v.borrow().element_type(), NumericVectorOp::Construct)
+            }
+            LValue::HashTable(_) => "hash-table",
+            LValue::Procedure(_) => "procedure",
+            LValue::Condition(_) => "condition",
+            LValue::Restart(_) => "restart",
+            LValue::Values(_) => "values",
+            LValue::Promise(_) => "promise",
+            LValue::Port(_) => "port",
+            LValue::Environment(_) => "environment",
+            LValue::Eof => "eof-object",
+            LValue::Undefined => "undefined",
+        }
+    }
+
+    /// The elements of a proper list, or `None` if the value isn't one.
+    pub fn list_items(&self) -> Option<Vec<LValue>> {
+        if !self.is_list() {
+            return None;
+        }
+        let mut items = Vec::new();
+        let mut rest = self.clone();
+        loop {
+            rest = match rest {
+                LValue::Pair(ref p) => {
+                    items.push(p.borrow().car.clone());
+                    p.borrow().cdr.clone()
                 }
+                _ => return Some(items),
             }
-            LValue::BooleanValue(b1) => {
-                match *rhs {
-                    LValue::BooleanValue(b2) => Ok(b1.cmp(&b2)),
-                    _ => Err("Expected boolean expression as the second argument.".to_string()),
+        }
+    }
+
+    /// The address of a pair or a vector, which identifies it.
+    fn address(&self) -> Option<usize> {
+        match *self {
+            LValue::Pair(ref p) => Some(p.as_ptr() as usize),
+            LValue::Vector(ref v) => Some(v.as_ptr() as usize),
+            _ => None,
+        }
+    }
+
+    /// The child at `k` of a pair or a vector: the car and the cdr, or the elements.
+    fn child(&self, k: usize) -> Option<LValue> {
+        match *self {
+            LValue::Pair(ref p) => {
+                let p = p.borrow();
+                match k {
+                    0 => Some(p.car.clone()),
+                    1 => Some(p.cdr.clone()),
+                    _ => None,
                 }
             }
-            LValue::NumericalValue(x1) => {
-                match *rhs {
-                    LValue::NumericalValue(x2) => {
-                        Ok(if x1 > x2 {
-                            Ordering::Greater
-                        } else if x1 == x2 {
-                            Ordering::Equal
-                        } else {
-                            Ordering::Less
-                        })
+            LValue::Vector(ref v) => v.borrow().get(k).cloned(),
+            _ => None,
+        }
+    }
+
+    /// Whether the value is a proper list. Circular lists aren't.
+    pub fn is_list(&self) -> bool {
+        let mut seen = HashSet::new();
+        let mut rest = self.clone();
+        loop {
+            rest = match rest {
+                LValue::Nil => return true,
+                LValue::Pair(ref p) => {
+                    if !seen.insert(p.as_ptr() as usize) {
+                        return false;
                     }
-                    _ => Err("Expected numerical expression as the second argument.".to_string()),
+                    p.borrow().cdr.clone()
                 }
+                _ => return false,
             }
-            LValue::Quoted(_) => Err("Can't compare quoted expressions.".to_string()),
-            LValue::Procedure(_) => Err("Can't compare procedures.".to_string()),
-            LValue::Undefined => Err("Can't compare #<undefined>'s.".to_string()),
+        }
+    }
+
+    /// Implements `eqv?`. Numbers aren't boxed, so this is also what `eq?` does.
+    pub fn eqv(&self, other: &LValue) -> bool {
+        match (self, other) {
+            (LValue::NumericalValue(a), LValue::NumericalValue(b)) => {
+                (a == b && a.is_sign_negative() == b.is_sign_negative()) ||
+                (a.is_nan() && b.is_nan())
+            }
+            (LValue::BooleanValue(a), LValue::BooleanValue(b)) => a == b,
+            (LValue::Character(a), LValue::Character(b)) => a == b,
+            (LValue::Symbol(a), LValue::Symbol(b)) => a == b,
+            (LValue::StringValue(a), LValue::StringValue(b)) => Rc::ptr_eq(a, b),
+            (LValue::Pair(a), LValue::Pair(b)) => Rc::ptr_eq(a, b),
+            (LValue::Vector(a), LValue::Vector(b)) => Rc::ptr_eq(a, b),
+            (LValue::Bytevector(a), LValue::Bytevector(b)) => Rc::ptr_eq(a, b),
+            (LValue::NumericVector(a), LValue::NumericVector(b)) => Rc::ptr_eq(a, b),
+            (LValue::HashTable(a), LValue::HashTable(b)) => Rc::ptr_eq(a, b),
+            (LValue::Procedure(a), LValue::Procedure(b)) => a.same(b),
+            (LValue::Condition(a), LValue::Condition(b)) => Rc::ptr_eq(a, b),
+            (LValue::Restart(a), LValue::Restart(b)) => a.id == b.id,
+            (LValue::Promise(a), LValue::Promise(b)) => Rc::ptr_eq(a, b),
+            (LValue::Port(a), LValue::Port(b)) => Rc::ptr_eq(a, b),
+            (LValue::Environment(a), LValue::Environment(b)) => Rc::ptr_eq(a, b),
+            (LValue::Nil, LValue::Nil) |
+            (LValue::Eof, LValue::Eof) |
+            (LValue::Undefined, LValue::Undefined) => true,
+            _ => false,
+        }
+    }
+
+    /// Implements `equal?`, which compares pairs, strings and the various kinds of vectors by
+    /// their contents.
+    pub fn equal(&self, other: &LValue) -> bool {
+        self.equal_visiting(other, &mut HashSet::new())
+    }
+
+    /// `visiting` holds the pairs of pairs and of vectors being compared further up. Finding
+    /// one of them again means the structures are circular, and equal unless shown otherwise
+    /// elsewhere.
+    fn equal_visiting(&self, other: &LValue, visiting: &mut HashSet<(usize, usize)>) -> bool {
+        let (mut a, mut b) = (self.clone(), other.clone());
+        loop {
+            let (next_a, next_b) = match (&a, &b) {
+                (LValue::Pair(p1), LValue::Pair(p2)) => {
+                    let key = (p1.as_ptr() as usize, p2.as_ptr() as usize);
+                    if !visiting.insert(key) {
+                        return true;
+                    }
+                    let (p1, p2) = (p1.borrow(), p2.borrow());
+                    if !p1.car.equal_visiting(&p2.car, visiting) {
+                        return false;
+                    }
+                    (p1.cdr.clone(), p2.cdr.clone())
+                }
+                (LValue::StringValue(s1), LValue::StringValue(s2)) => {
+                    return *s1.borrow() == *s2.borrow()
+                }
+                (LValue::Bytevector(b1), LValue::Bytevector(b2)) => {
+                    return *b1.borrow() == *b2.borrow()
+                }
+                (LValue::NumericVector(v1), LValue::NumericVector(v2)) => {
+                    return Rc::ptr_eq(v1, v2) || v1.borrow().eqv_elements(&v2.borrow())
+                }
+                (LValue::Vector(v1), LValue::Vector(v2)) => {
+                    let key = (v1.as_ptr() as usize, v2.as_ptr() as usize);
+                    if !visiting.insert(key) {
+                        return true;
+                    }
+                    let (v1, v2) = (v1.borrow(), v2.borrow());
+                    return v1.len() == v2.len() &&
+                           v1.iter().zip(v2.iter()).all(|(a, b)| a.equal_visiting(b, visiting));
+                }
+                _ => return a.eqv(&b),
+            };
+            a = next_a;
+            b = next_b;
         }
     }
 
@@ -104,51 +1421,147 @@ impl LValue {
         match *self {
             LValue::NumericalValue(x) => Ok(x >= 0.0),
             LValue::BooleanValue(b) => Ok(b),
-            LValue::StringValue(_) => Ok(true),
-            LValue::Quoted(_) => Ok(true),
+            LValue::StringValue(_) | LValue::Character(_) | LValue::Bytevector(_) => Ok(true),
+            LValue::Symbol(_) | LValue::Pair(_) | LValue::Nil | LValue::Vector(_) => Ok(true),
+            LValue::Condition(_) | LValue::Restart(_) => Ok(true),
+            LValue::Promise(_) | LValue::Port(_) | LValue::Eof => Ok(true),
+            LValue::Environment(_) | LValue::NumericVector(_) | LValue::HashTable(_) => Ok(true),
+            LValue::Values(_) => Err("Can't convert multiple values to a boolean.".to_string()),
             _ => Err("Can't convert procedures and #undefined's to booleans.".to_string()),
         }
     }
 }
 
+/// A sequence of expressions, such as the body of a procedure. The expressions are shared,
+/// so that the evaluator can keep its place in a body while it evaluates it.
+pub type Body = Rc<[Rc<Expression>]>;
+
 #[derive(Debug,Clone)]
 pub enum Expression {
-    List(Vec<Expression>),
-    Eval(Box<Expression>),
+    List(Body),
     Definition {
         name: String,
-        value: Box<Expression>,
+        value: Rc<Expression>,
     },
-    Lambda(Procedure),
+    Lambda(Rc<Lambda>),
     Identifier(String),
     Value(LValue),
     IfCondition {
-        cond: Box<Expression>,
-        yes_expr: Box<Expression>,
-        no_expr: Option<Box<Expression>>,
+        cond: Rc<Expression>,
+        yes_expr: Rc<Expression>,
+        no_expr: Option<Rc<Expression>>,
+    },
+    RestartCase {
+        body: Rc<Expression>,
+        clauses: Rc<[RestartClause]>,
+    },
+    LetValues {
+        bindings: Rc<[(Formals, Rc<Expression>)]>,
+        body: Body,
+        sequential: bool,
+    },
+    DefineValues {
+        formals: Formals,
+        value: Rc<Expression>,
+    },
+    Delay {
+        body: Rc<Expression>,
+        delay_force: bool,
+    },
+    /// The forms of the files named by an `include`, read when the `include` was.
+    Include(Body),
+    DefineLibrary(Rc<LibraryDefinition>),
+    CondExpand(Vec<(Requirement, Body)>),
+    Import(Vec<ImportSet>),
+    Parameterize {
+        bindings: Rc<[(Rc<Expression>, Rc<Expression>)]>,
+        body: Body,
     },
 }
 
+/// A restart established by `restart-case`: `(name (arguments...) ["description"] body...)`.
+#[derive(Debug,Clone)]
+pub struct RestartClause {
+    pub name: String,
+    pub description: String,
+    pub arguments: Vec<String>,
+    pub body: Body,
+}
+
 impl Expression {
-    pub fn from_list(l: &ListNode) -> Result<Expression, String> {
+    /// Parses a form at the top level of a program, where `import` and `define-library` are
+    /// allowed too, as are `cond-expand` clauses made of top-level forms.
+    pub fn from_top_level(l: &ListNode, directory: &Path) -> Result<Expression, String> {
+        if let ListNode::Node(ref v) = *l {
+            if let Some(ListNode::Identifier(s)) = v.first() {
+                match s.as_str() {
+                    "define-library" => {
+                        return Expression::process_define_library(&v[1..], directory)
+                    }
+                    "import" => return Expression::process_import(&v[1..]),
+                    "cond-expand" => {
+                        let clauses = Expression::process_cond_expand_clauses(&v[1..], |body| {
+                                body.iter()
+                                    .map(|f| Expression::from_top_level(f, directory).map(Rc::new))
+                                    .collect()
+                            })?;
+                        return Ok(Expression::CondExpand(clauses));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Expression::from_list(l, directory)
+    }
+
+    pub fn from_list(l: &ListNode, directory: &Path) -> Result<Expression, String> {
         match *l {
-            ListNode::StringLiteral(ref s) => Ok(Expression::Value(LValue::StringValue(s.clone()))),
+            ListNode::StringLiteral(_) => Ok(Expression::Value(LValue::from_literal(l))),
             ListNode::BooleanLiteral(b) => Ok(Expression::Value(LValue::BooleanValue(b))),
+            ListNode::CharLiteral(c) => Ok(Expression::Value(LValue::Character(c))),
+            ListNode::Vector(_) | ListNode::Bytevector(_) | ListNode::NumericVector(..) => {
+                Ok(Expression::Value(LValue::from_literal(l)))
+            }
             ListNode::NumericLiteral(v) => Ok(Expression::Value(LValue::NumericalValue(v))),
-            ListNode::Node(quoted, ref v) => {
-                if quoted {
-                    let newv = (*v).clone();
-                    return Expression::process_quote(&ListNode::Node(false, newv));
-                }
-                if v.len() == 0 {
-                    return Ok(Expression::List(Vec::new()));
+            ListNode::Node(ref v) => {
+                if v.is_empty() {
+                    return Ok(Expression::List(Rc::new([])));
                 }
                 match v[0] {
-                    ListNode::Identifier(_, ref s) => {
+                    ListNode::Identifier(ref s) => {
                         match s.as_str() {
-                            "lambda" => Expression::process_lambda(&v[1..]),
-                            "define" => Expression::process_define(&v[1..]),
-                            "if" => Expression::process_if(&v[1..]),
+                            "lambda" => Expression::process_lambda(&v[1..], directory),
+                            "define" => Expression::process_define(&v[1..], directory),
+                            "if" => Expression::process_if(&v[1..], directory),
+                            "restart-case" => Expression::process_restart_case(&v[1..], directory),
+                            "let-values" => {
+                                Expression::process_let_values(&v[1..], false, directory)
+                            }
+                            "let*-values" => {
+                                Expression::process_let_values(&v[1..], true, directory)
+                            }
+                            "define-values" => {
+                                Expression::process_define_values(&v[1..], directory)
+                            }
+                            "receive" => Expression::process_receive(&v[1..], directory),
+                            "delay" => Expression::process_delay(&v[1..], false, directory),
+                            "delay-force" => Expression::process_delay(&v[1..], true, directory),
+                            "stream-cons" => Expression::process_stream_cons(&v[1..], directory),
+                            "parameterize" => Expression::process_parameterize(&v[1..], directory),
+                            "define-library" | "import" => {
+                                Err(format!("'{}' is only allowed at the top level of a \
+                                             program or library.",
+                                            s))
+                            }
+                            "include" => Expression::process_include(&v[1..], false, directory),
+                            "include-ci" => Expression::process_include(&v[1..], true, directory),
+                            "cond-expand" => {
+                                let clauses =
+                                    Expression::process_cond_expand_clauses(&v[1..], |body| {
+                                            Expression::process_body(body, directory)
+                                        })?;
+                                Ok(Expression::CondExpand(clauses))
+                            }
                             "quote" => {
                                 if v.len() != 2 {
                                     Err("Quote expression must contain exactly one expression."
@@ -157,47 +1570,29 @@ impl Expression {
                                     Expression::process_quote(&v[1])
                                 }
                             }
-                            "eval" => {
-                                if v.len() != 2 {
-                                    Err("Eval expression must contain exactly one expression."
-                                        .to_string())
-                                } else {
-                                    Expression::process_eval(&v[1])
-                                }
-                            }
-                            _ => Expression::process_list(&v),
+                            _ => Expression::process_list(v, directory),
                         }
                     }
-                    _ => Expression::process_list(&v),
-                }
-            }
-            ListNode::Identifier(quoted, ref s) => {
-                if !quoted {
-                    Ok(Expression::Identifier(s.to_string()))
-                } else {
-                    Expression::process_quote(&ListNode::Identifier(false, s.to_string()))
+                    _ => Expression::process_list(v, directory),
                 }
             }
+            ListNode::Identifier(ref s) => Ok(Expression::Identifier(s.to_string())),
         }
     }
 
-    fn process_define(params: &[ListNode]) -> Result<Expression, String> {
+    fn process_define(params: &[ListNode], directory: &Path) -> Result<Expression, String> {
         if params.len() != 2 {
             Err("A definition statement needs exactly 2 arguments.".to_string())
         } else {
-            if let ListNode::Identifier(quoted, ref s) = params[0] {
-                if quoted {
-                    Err("Identifier name in define statement must not be quoted.".to_string())
-                } else {
-                    match Expression::from_list(&params[1]) {
-                        Ok(e) => {
-                            Ok(Expression::Definition {
-                                name: s.to_string(),
-                                value: Box::new(e),
-                            })
-                        }
-                        Err(s) => Err(s),
+            if let ListNode::Identifier(ref s) = params[0] {
+                match Expression::from_list(&params[1], directory) {
+                    Ok(e) => {
+                        Ok(Expression::Definition {
+                            name: s.to_string(),
+                            value: Rc::new(e),
+                        })
                     }
+                    Err(s) => Err(s),
                 }
             } else {
                 Err("First argument must be a valid identifier.".to_string())
@@ -205,92 +1600,394 @@ impl Expression {
         }
     }
 
-    fn process_lambda(params: &[ListNode]) -> Result<Expression, String> {
+    fn process_lambda(params: &[ListNode], directory: &Path) -> Result<Expression, String> {
+        if params.is_empty() {
+            return Err("A lambda expression needs an argument list.".to_string());
+        }
+        let args = Expression::process_formals(&params[0])?;
+        let body = Expression::process_body(&params[1..], directory)?;
+        Ok(Expression::Lambda(Rc::new(Lambda {
+            arguments: args,
+            body,
+        })))
+    }
+
+    fn process_body(forms: &[ListNode], directory: &Path) -> Result<Body, String> {
+        forms.iter().map(|v| Expression::from_list(v, directory).map(Rc::new)).collect()
+    }
+
+    fn process_formals(arg_list: &ListNode) -> Result<Formals, String> {
         let mut args: Vec<String> = Vec::new();
-        let arg_list = &params[0];
-        if let ListNode::Node(quoted, ref v) = *arg_list {
-            if quoted {
-                return Err("The argument list in a lambda expression must not be quoted."
-                    .to_string());
-            }
-            for a in v {
-                if let ListNode::Identifier(quoted, ref s) = *a {
-                    if quoted {
-                        return Err("Argument name must not be quoted in a lambda expression"
+        let mut rest: Option<String> = None;
+        match *arg_list {
+            ListNode::Identifier(ref s) => rest = Some(s.to_string()),
+            ListNode::Node(ref v) => {
+                let mut dotted = false;
+                for (i, a) in v.iter().enumerate() {
+                    if let ListNode::Identifier(ref s) = *a {
+                        if s == "." {
+                            if dotted || i + 2 != v.len() {
+                                return Err("A single rest argument must follow the dot."
+                                    .to_string());
+                            }
+                            dotted = true;
+                        } else if dotted {
+                            rest = Some(s.to_string());
+                        } else {
+                            args.push(s.to_string());
+                        }
+                    } else {
+                        return Err("The argument list must only contain identifiers."
                             .to_string());
                     }
-                    args.push(s.to_string());
-                } else {
-                    return Err("The argument list must only contain non-quoted identifiers."
-                        .to_string());
                 }
             }
-        }
-        let mut body: Vec<Expression> = Vec::new();
-        for v in &params[1..] {
-            match Expression::from_list(v) {
-                Ok(exp) => body.push(exp),
-                Err(s) => return Err(s),
+            _ => {
+                return Err("The argument list must only contain identifiers.".to_string())
             }
         }
-        Ok(Expression::Lambda(Procedure::UserDefined {
-            arguments: args,
-            body: body,
-        }))
+        Ok(Formals {
+            required: args,
+            rest,
+        })
     }
 
-    fn process_if(params: &[ListNode]) -> Result<Expression, String> {
+    fn process_if(params: &[ListNode], directory: &Path) -> Result<Expression, String> {
         if params.len() < 2 || params.len() > 3 {
             return Err("'if' statement requires two or three expressions.".to_string());
         }
-        let condition: Box<Expression>;
-        let yes_expr: Box<Expression>;
-        let no_expr: Option<Box<Expression>>;
-        match Expression::from_list(&params[0]) {
-            Ok(ref e) => condition = Box::new(e.clone()),
+        let condition: Rc<Expression> = match Expression::from_list(&params[0], directory) {
+            Ok(e) => Rc::new(e),
             Err(s) => return Err(s),
-        }
-        match Expression::from_list(&params[1]) {
-            Ok(ref e) => yes_expr = Box::new(e.clone()),
+        };
+        let yes_expr: Rc<Expression> = match Expression::from_list(&params[1], directory) {
+            Ok(e) => Rc::new(e),
             Err(s) => return Err(s),
-        }
-        if params.len() < 3 {
-            no_expr = Option::None;
+        };
+        let no_expr: Option<Rc<Expression>> = if params.len() < 3 {
+            Option::None
         } else {
-            match Expression::from_list(&params[2]) {
-                Ok(ref e) => no_expr = Some(Box::new(e.clone())),
+            match Expression::from_list(&params[2], directory) {
+                Ok(e) => Some(Rc::new(e)),
                 Err(s) => return Err(s),
             }
-        }
+        };
         Ok(Expression::IfCondition {
             cond: condition,
-            yes_expr: yes_expr,
-            no_expr: no_expr,
+            yes_expr,
+            no_expr,
         })
     }
 
-    fn process_quote(n: &ListNode) -> Result<Expression, String> {
-        match Expression::from_list(n) {
-            Ok(e) => Ok(Expression::Value(LValue::Quoted(Box::new(e)))),
-            Err(s) => Err(s),
+    fn process_restart_case(params: &[ListNode], directory: &Path) -> Result<Expression, String> {
+        if params.is_empty() {
+            return Err("'restart-case' requires an expression to evaluate.".to_string());
+        }
+        let body = Expression::from_list(&params[0], directory)?;
+        let mut clauses: Vec<RestartClause> = Vec::new();
+        for clause in &params[1..] {
+            let parts = match *clause {
+                ListNode::Node(ref parts) if parts.len() >= 2 => parts,
+                _ => {
+                    return Err("A restart clause must contain a name and an argument list."
+                        .to_string())
+                }
+            };
+            let name = match parts[0] {
+                ListNode::Identifier(ref s) => s.clone(),
+                _ => return Err("The name of a restart must be an identifier.".to_string()),
+            };
+            let formals = Expression::process_formals(&parts[1])?;
+            if formals.rest.is_some() {
+                return Err("A restart can't take a rest argument.".to_string());
+            }
+            let arguments = formals.required;
+            let mut forms = &parts[2..];
+            let mut description = name.clone();
+            if forms.len() > 1 {
+                if let ListNode::StringLiteral(ref s) = forms[0] {
+                    description = s.clone();
+                    forms = &forms[1..];
+                }
+            }
+            let body = Expression::process_body(forms, directory)?;
+            clauses.push(RestartClause {
+                name,
+                description,
+                arguments,
+                body,
+            });
+        }
+        Ok(Expression::RestartCase {
+            body: Rc::new(body),
+            clauses: clauses.into(),
+        })
+    }
+
+    fn process_let_values(params: &[ListNode],
+                          sequential: bool,
+                          directory: &Path)
+                          -> Result<Expression, String> {
+        if params.len() < 2 {
+            return Err("'let-values' requires a list of bindings and a body.".to_string());
+        }
+        let mut bindings = Vec::new();
+        match params[0] {
+            ListNode::Node(ref v) => {
+                for binding in v {
+                    match *binding {
+                        ListNode::Node(ref b) if b.len() == 2 => {
+                            bindings.push((Expression::process_formals(&b[0])?,
+                                           Rc::new(Expression::from_list(&b[1], directory)?)));
+                        }
+                        _ => {
+                            return Err("Each binding of 'let-values' must be of the form \
+                                        (formals expression)."
+                                .to_string())
+                        }
+                    }
+                }
+            }
+            _ => return Err("'let-values' requires a list of bindings.".to_string()),
+        }
+        Ok(Expression::LetValues {
+            bindings: bindings.into(),
+            body: Expression::process_body(&params[1..], directory)?,
+            sequential,
+        })
+    }
+
+    fn process_receive(params: &[ListNode], directory: &Path) -> Result<Expression, String> {
+        if params.len() < 3 {
+            return Err("'receive' requires formals, an expression and a body.".to_string());
+        }
+        Ok(Expression::LetValues {
+            bindings: Rc::new([(Expression::process_formals(&params[0])?,
+                                Rc::new(Expression::from_list(&params[1], directory)?))]),
+            body: Expression::process_body(&params[2..], directory)?,
+            sequential: false,
+        })
+    }
+
+    fn process_define_values(params: &[ListNode], directory: &Path) -> Result<Expression, String> {
+        if params.len() != 2 {
+            return Err("'define-values' requires formals and an expression.".to_string());
+        }
+        Ok(Expression::DefineValues {
+            formals: Expression::process_formals(&params[0])?,
+            value: Rc::new(Expression::from_list(&params[1], directory)?),
+        })
+    }
+
+    fn process_delay(params: &[ListNode],
+                     delay_force: bool,
+                     directory: &Path)
+                     -> Result<Expression, String> {
+        if params.len() != 1 {
+            return Err("A delayed expression must contain exactly one expression.".to_string());
+        }
+        Ok(Expression::Delay {
+            body: Rc::new(Expression::from_list(&params[0], directory)?),
+            delay_force,
+        })
+    }
+
+    /// `(stream-cons a b)` stands for `(delay (cons (delay a) (delay-force b)))`.
+    fn process_stream_cons(params: &[ListNode], directory: &Path) -> Result<Expression, String> {
+        if params.len() != 2 {
+            return Err("'stream-cons' requires exactly two expressions.".to_string());
+        }
+        let cons = Expression::Value(LValue::Procedure(Procedure::Cons));
+        let head = Expression::process_delay(&params[0..1], false, directory)?;
+        let tail = Expression::process_delay(&params[1..2], true, directory)?;
+        let pair = Expression::List(Rc::new([Rc::new(cons), Rc::new(head), Rc::new(tail)]));
+        Ok(Expression::Delay {
+            body: Rc::new(pair),
+            delay_force: false,
+        })
+    }
+
+    fn process_parameterize(params: &[ListNode], directory: &Path) -> Result<Expression, String> {
+        if params.len() < 2 {
+            return Err("'parameterize' requires a list of bindings and a body.".to_string());
         }
+        let mut bindings = Vec::new();
+        match params[0] {
+            ListNode::Node(ref v) => {
+                for binding in v {
+                    match *binding {
+                        ListNode::Node(ref b) if b.len() == 2 => {
+                            bindings.push((Rc::new(Expression::from_list(&b[0], directory)?),
+                                           Rc::new(Expression::from_list(&b[1], directory)?)));
+                        }
+                        _ => {
+                            return Err("Each binding of 'parameterize' must be of the form \
+                                        (parameter value)."
+                                .to_string())
+                        }
+                    }
+                }
+            }
+            _ => return Err("'parameterize' requires a list of bindings.".to_string()),
+        }
+        Ok(Expression::Parameterize {
+            bindings: bindings.into(),
+            body: Expression::process_body(&params[1..], directory)?,
+        })
+    }
+
+    fn process_define_library(params: &[ListNode], directory: &Path) -> Result<Expression, String> {
+        if params.is_empty() {
+            return Err("'define-library' requires a library name.".to_string());
+        }
+        Ok(Expression::DefineLibrary(Rc::new(LibraryDefinition {
+            name: parse_library_name(&params[0])?,
+            declarations: Expression::process_library_declarations(&params[1..], directory)?,
+        })))
+    }
+
+    fn process_library_declarations(declarations: &[ListNode], directory: &Path)
+                                    -> Result<Vec<LibraryDeclaration>, String> {
+        let mut result = Vec::new();
+        for declaration in declarations {
+            let (keyword, rest) = match *declaration {
+                ListNode::Node(ref v) if !v.is_empty() => {
+                    match v[0] {
+                        ListNode::Identifier(ref s) => (s.as_str(), &v[1..]),
+                        _ => ("", &v[1..]),
+                    }
+                }
+                _ => ("", &declarations[..0]),
+            };
+            match keyword {
+                "export" => {
+                    let mut exports = Vec::new();
+                    for spec in rest {
+                        exports.push(match *spec {
+                            ListNode::Identifier(ref s) => (s.clone(), s.clone()),
+                            ListNode::Node(ref v) if v.len() == 3 => {
+                                match v[0] {
+                                    ListNode::Identifier(ref s) if s == "rename" => {
+                                        parse_rename(&ListNode::Node(v[1..].to_vec()))?
+                                    }
+                                    _ => return Err("Invalid export specification.".to_string()),
+                                }
+                            }
+                            _ => return Err("Invalid export specification.".to_string()),
+                        });
+                    }
+                    result.push(LibraryDeclaration::Export(exports));
+                }
+                "import" => {
+                    let import = Expression::process_import(rest)?;
+                    result.push(LibraryDeclaration::Form(Rc::new(import)));
+                }
+                "include" | "include-ci" => {
+                    let fold_case = keyword == "include-ci";
+                    let include = Expression::process_include(rest, fold_case, directory)?;
+                    result.push(LibraryDeclaration::Form(Rc::new(include)));
+                }
+                "begin" => {
+                    for e in Expression::process_body(rest, directory)?.iter() {
+                        result.push(LibraryDeclaration::Form(e.clone()));
+                    }
+                }
+                "cond-expand" => {
+                    let clauses = Expression::process_cond_expand_clauses(rest, |body| {
+                            Expression::process_library_declarations(body, directory)
+                        })?;
+                    result.push(LibraryDeclaration::CondExpand(clauses));
+                }
+                _ => return Err("Invalid library declaration.".to_string()),
+            }
+        }
+        Ok(result)
     }
 
-    fn process_eval(n: &ListNode) -> Result<Expression, String> {
-        match Expression::from_list(n) {
-            Ok(e) => Ok(Expression::Eval(Box::new(e))),
-            Err(s) => Err(s),
+    /// Parses the clauses of a `cond-expand`, whose bodies are either expressions or library
+    /// declarations.
+    fn process_cond_expand_clauses<B, F>(params: &[ListNode],
+                                         process_body: F)
+                                         -> Result<Vec<(Requirement, B)>, String>
+        where F: Fn(&[ListNode]) -> Result<B, String>
+    {
+        let mut clauses = Vec::new();
+        for clause in params {
+            match *clause {
+                ListNode::Node(ref v) if !v.is_empty() => {
+                    clauses.push((Requirement::parse(&v[0])?, process_body(&v[1..])?));
+                }
+                _ => {
+                    return Err("Each clause of 'cond-expand' must be of the form \
+                                (requirement body...)."
+                        .to_string())
+                }
+            }
+        }
+        Ok(clauses)
+    }
+
+    /// Reads the files named by an `include`, relative to `directory` and as if in lower case
+    /// with `fold_case`, and expands their forms in place of it.
+    fn process_include(params: &[ListNode],
+                       fold_case: bool,
+                       directory: &Path)
+                       -> Result<Expression, String> {
+        if params.is_empty() {
+            return Err("'include' requires file names.".to_string());
+        }
+        let mut forms = Vec::new();
+        for param in params {
+            let path = match *param {
+                ListNode::StringLiteral(ref s) => directory.join(s),
+                _ => return Err("'include' requires file names.".to_string()),
+            };
+            let mut port = Port::open_input_file(&path.to_string_lossy())
+                .map_err(|e| format!("Can't open file '{}': {}.", path.display(), e))?;
+            port.fold_case = fold_case;
+            // The files that this one includes are found relative to it in turn.
+            let directory = path.parent().unwrap_or(directory);
+            Expression::process_port(&mut port, &mut forms, directory)?;
         }
+        Ok(Expression::Include(forms.into()))
+    }
+
+    /// Reads every form left in the port, and adds its expansion to `forms`.
+    fn process_port(port: &mut Port,
+                    forms: &mut Vec<Rc<Expression>>,
+                    directory: &Path)
+                    -> Result<(), String> {
+        loop {
+            match ListNode::read(port) {
+                Ok(Some(datum)) => forms.push(Rc::new(Expression::from_list(&datum, directory)?)),
+                Ok(None) => return Ok(()),
+                Err(s) => {
+                    return Err(match port.location() {
+                        Some(location) => format!("{}: {}", location, s),
+                        None => s,
+                    })
+                }
+            }
+        }
+    }
+
+    fn process_import(params: &[ListNode]) -> Result<Expression, String> {
+        let sets = params.iter().map(ImportSet::parse).collect::<Result<Vec<_>, _>>()?;
+        Ok(Expression::Import(sets))
+    }
+
+    fn process_quote(n: &ListNode) -> Result<Expression, String> {
+        Ok(Expression::Value(LValue::from_literal(n)))
     }
 
-    fn process_list(elements: &[ListNode]) -> Result<Expression, String> {
-        let mut children: Vec<Expression> = Vec::new();
+    fn process_list(elements: &[ListNode], directory: &Path) -> Result<Expression, String> {
+        let mut children: Vec<Rc<Expression>> = Vec::new();
         for e in elements {
-            match Expression::from_list(e) {
-                Ok(expr) => children.push(expr),
+            match Expression::from_list(e, directory) {
+                Ok(expr) => children.push(Rc::new(expr)),
                 Err(s) => return Err(s),
             }
         }
-        Ok(Expression::List(children))
+        Ok(Expression::List(children.into()))
     }
 }
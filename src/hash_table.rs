@@ -0,0 +1,165 @@
+use expression::LValue;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::rc::Rc;
+
+/// How many values `hash` looks at inside a structure. Circular structures would have it
+/// go on forever otherwise.
+const HASHED_VALUES: usize = 64;
+
+/// The equivalence a hash table compares its keys with.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Equivalence {
+    Equal,
+    Eqv,
+    /// `string=?`, for tables whose keys are all strings.
+    StringEqual,
+}
+
+/// A hash table, as made by `make-hash-table`. Keys with the same hash share a bucket.
+#[derive(Debug)]
+pub struct HashTable {
+    pub equivalence: Equivalence,
+    buckets: HashMap<u64, Vec<(LValue, LValue)>>,
+    size: usize,
+}
+
+impl HashTable {
+    pub fn new(equivalence: Equivalence) -> HashTable {
+        HashTable {
+            equivalence,
+            buckets: HashMap::new(),
+            size: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    fn hash(&self, key: &LValue) -> u64 {
+        match self.equivalence {
+            Equivalence::Eqv => hash_eqv(key),
+            Equivalence::Equal | Equivalence::StringEqual => hash_equal(key),
+        }
+    }
+
+    fn same_key(&self, a: &LValue, b: &LValue) -> bool {
+        match self.equivalence {
+            Equivalence::Eqv => a.eqv(b),
+            Equivalence::Equal | Equivalence::StringEqual => a.equal(b),
+        }
+    }
+
+    /// Where the entry for `key`, whose hash is `hash`, is in its bucket.
+    fn position(&self, hash: u64, key: &LValue) -> Option<usize> {
+        self.buckets.get(&hash)?.iter().position(|(k, _)| self.same_key(k, key))
+    }
+
+    pub fn get(&self, key: &LValue) -> Option<LValue> {
+        let hash = self.hash(key);
+        let i = self.position(hash, key)?;
+        Some(self.buckets[&hash][i].1.clone())
+    }
+
+    pub fn insert(&mut self, key: LValue, value: LValue) {
+        let hash = self.hash(&key);
+        match self.position(hash, &key) {
+            Some(i) => self.buckets.get_mut(&hash).unwrap()[i].1 = value,
+            None => {
+                self.buckets.entry(hash).or_default().push((key, value));
+                self.size += 1;
+            }
+        }
+    }
+
+    /// Removes the entry for `key`, returning whether there was one.
+    pub fn remove(&mut self, key: &LValue) -> bool {
+        let hash = self.hash(key);
+        let i = match self.position(hash, key) {
+            Some(i) => i,
+            None => return false,
+        };
+        let bucket = self.buckets.get_mut(&hash).unwrap();
+        bucket.swap_remove(i);
+        if bucket.is_empty() {
+            self.buckets.remove(&hash);
+        }
+        self.size -= 1;
+        true
+    }
+
+    /// The keys and values in the table, in no particular order.
+    pub fn entries(&self) -> Vec<(LValue, LValue)> {
+        self.buckets.values().flat_map(|bucket| bucket.iter().cloned()).collect()
+    }
+}
+
+/// A hash consistent with `equal?`: values that are `equal?` hash the same.
+pub fn hash_equal(value: &LValue) -> u64 {
+    let mut state = DefaultHasher::new();
+    let mut budget = HASHED_VALUES;
+    hash_into(value, true, &mut budget, &mut state);
+    state.finish()
+}
+
+/// A hash consistent with `eqv?`, which only looks at the identity of boxed values.
+pub fn hash_eqv(value: &LValue) -> u64 {
+    let mut state = DefaultHasher::new();
+    let mut budget = HASHED_VALUES;
+    hash_into(value, false, &mut budget, &mut state);
+    state.finish()
+}
+
+/// Feeds `value` to the hasher, going into strings, pairs and vectors if `contents` is set.
+/// `budget` is the number of values left to look at.
+fn hash_into<H: Hasher>(value: &LValue, contents: bool, budget: &mut usize, state: &mut H) {
+    if *budget == 0 {
+        return;
+    }
+    *budget -= 1;
+    mem::discriminant(value).hash(state);
+    match *value {
+        // All NaNs are `eqv?` to each other.
+        LValue::NumericalValue(x) => if x.is_nan() { 0 } else { x.to_bits() }.hash(state),
+        LValue::BooleanValue(b) => b.hash(state),
+        LValue::Character(c) => c.hash(state),
+        LValue::Symbol(ref s) => s.hash(state),
+        LValue::StringValue(ref s) if contents => s.borrow().hash(state),
+        LValue::Bytevector(ref v) if contents => v.borrow().hash(state),
+        LValue::NumericVector(ref v) if contents => {
+            let v = v.borrow();
+            v.element_type().tag().hash(state);
+            // The elements compare as `eqv?` does, like numbers on their own.
+            for x in v.to_f64s() {
+                if x.is_nan() { 0 } else { x.to_bits() }.hash(state);
+            }
+        }
+        LValue::Pair(ref p) if contents => {
+            let p = p.borrow();
+            hash_into(&p.car, contents, budget, state);
+            hash_into(&p.cdr, contents, budget, state);
+        }
+        LValue::Vector(ref v) if contents => {
+            for item in v.borrow().iter() {
+                hash_into(item, contents, budget, state);
+            }
+        }
+        LValue::StringValue(ref s) => (s.as_ptr() as usize).hash(state),
+        LValue::Bytevector(ref v) => (v.as_ptr() as usize).hash(state),
+        LValue::NumericVector(ref v) => (v.as_ptr() as usize).hash(state),
+        LValue::Pair(ref p) => (p.as_ptr() as usize).hash(state),
+        LValue::Vector(ref v) => (v.as_ptr() as usize).hash(state),
+        LValue::HashTable(ref t) => (t.as_ptr() as usize).hash(state),
+        LValue::Port(ref port) => (port.as_ptr() as usize).hash(state),
+        LValue::Environment(ref e) => (e.as_ptr() as usize).hash(state),
+        LValue::Condition(ref c) => (Rc::as_ptr(c) as usize).hash(state),
+        LValue::Promise(ref promise) => (Rc::as_ptr(promise) as usize).hash(state),
+        LValue::Restart(ref r) => r.id.hash(state),
+        // Builtins with the same name are the same procedure.
+        LValue::Procedure(ref p) => p.name().hash(state),
+        LValue::Nil | LValue::Values(_) | LValue::Eof | LValue::Undefined => {}
+    }
+}
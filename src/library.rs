@@ -0,0 +1,185 @@
+use expression::*;
+use list::ListNode;
+use std::collections::HashMap;
+use std::env;
+use std::rc::Rc;
+
+/// A library defined by `define-library`, as seen from the code that imports it.
+pub struct Library {
+    pub exports: HashMap<String, LValue>,
+}
+
+/// The parsed form of a `define-library`.
+#[derive(Debug)]
+pub struct LibraryDefinition {
+    pub name: Vec<String>,
+    pub declarations: Vec<LibraryDeclaration>,
+}
+
+/// A declaration in the body of a `define-library`.
+#[derive(Debug)]
+pub enum LibraryDeclaration {
+    /// Exported names, as `(internal, external)` pairs.
+    Export(Vec<(String, String)>),
+    /// An `import` or `include` declaration, or one of the forms of a `begin` declaration.
+    Form(Rc<Expression>),
+    CondExpand(Vec<(Requirement, Vec<LibraryDeclaration>)>),
+}
+
+/// The feature requirement of a `cond-expand` clause.
+#[derive(Debug,Clone)]
+pub enum Requirement {
+    Feature(String),
+    Library(Vec<String>),
+    And(Vec<Requirement>),
+    Or(Vec<Requirement>),
+    Not(Box<Requirement>),
+    Else,
+}
+
+/// The R7RS libraries provided by the builtins and the standard library, as the names that
+/// follow `scheme` in `(scheme base)` and the like.
+const STANDARD_LIBRARIES: [&str; 10] = ["base",
+                                        "char",
+                                        "eval",
+                                        "file",
+                                        "lazy",
+                                        "load",
+                                        "process-context",
+                                        "read",
+                                        "repl",
+                                        "write"];
+
+/// Whether `name` is one of the standard libraries, such as `(scheme base)`.
+pub fn is_standard_library(name: &[String]) -> bool {
+    name.len() == 2 && name[0] == "scheme" && STANDARD_LIBRARIES.contains(&name[1].as_str())
+}
+
+/// The features `cond-expand` recognizes, as listed by `features`.
+pub fn features() -> Vec<String> {
+    let mut features: Vec<String> =
+        ["r7rs", "rscheme", "full-unicode", "srfi-4", "srfi-6", "srfi-23", "srfi-39", "srfi-41",
+         "srfi-69"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+    if cfg!(unix) {
+        features.push("posix".to_string());
+    }
+    features.push(env::consts::OS.to_string());
+    features.push(env::consts::ARCH.to_string());
+    features.push(if cfg!(target_endian = "little") {
+            "little-endian"
+        } else {
+            "big-endian"
+        }
+        .to_string());
+    features
+}
+
+/// An import set, such as `(prefix (only (foo bar) baz) foo:)`.
+#[derive(Debug,Clone)]
+pub enum ImportSet {
+    Library(Vec<String>),
+    Only(Box<ImportSet>, Vec<String>),
+    Except(Box<ImportSet>, Vec<String>),
+    Prefix(Box<ImportSet>, String),
+    Rename(Box<ImportSet>, Vec<(String, String)>),
+}
+
+/// Formats a library name the way it's written, e.g. `(foo bar)`.
+pub fn library_key(name: &[String]) -> String {
+    format!("({})", name.join(" "))
+}
+
+fn identifier(n: &ListNode) -> Result<String, String> {
+    match *n {
+        ListNode::Identifier(ref s) => Ok(s.clone()),
+        _ => Err("Expected an identifier in an import set.".to_string()),
+    }
+}
+
+/// Parses a pair of names, as in `(rename (a b))` or `(export (rename a b))`.
+pub fn parse_rename(n: &ListNode) -> Result<(String, String), String> {
+    match *n {
+        ListNode::Node(ref v) if v.len() == 2 => Ok((identifier(&v[0])?, identifier(&v[1])?)),
+        _ => Err("A renaming must be of the form (old-name new-name).".to_string()),
+    }
+}
+
+pub fn parse_library_name(n: &ListNode) -> Result<Vec<String>, String> {
+    let parts = match *n {
+        ListNode::Node(ref v) if !v.is_empty() => v,
+        _ => return Err("A library name must be a non-empty list.".to_string()),
+    };
+    let mut name = Vec::new();
+    for part in parts {
+        name.push(match *part {
+            ListNode::Identifier(ref s) => s.clone(),
+            ListNode::NumericLiteral(v) if v >= 0.0 && v.fract() == 0.0 => v.to_string(),
+            _ => {
+                return Err("A library name may only contain identifiers and non-negative \
+                            integers."
+                    .to_string())
+            }
+        });
+    }
+    Ok(name)
+}
+
+impl Requirement {
+    pub fn parse(n: &ListNode) -> Result<Requirement, String> {
+        let v = match *n {
+            ListNode::Identifier(ref s) if s == "else" => return Ok(Requirement::Else),
+            ListNode::Identifier(ref s) => return Ok(Requirement::Feature(s.clone())),
+            ListNode::Node(ref v) if !v.is_empty() => v,
+            _ => return Err("Invalid feature requirement.".to_string()),
+        };
+        let requirements = || v[1..].iter().map(Requirement::parse).collect::<Result<Vec<_>, _>>();
+        match v[0] {
+            ListNode::Identifier(ref s) if s == "and" => Ok(Requirement::And(requirements()?)),
+            ListNode::Identifier(ref s) if s == "or" => Ok(Requirement::Or(requirements()?)),
+            ListNode::Identifier(ref s) if s == "not" && v.len() == 2 => {
+                Ok(Requirement::Not(Box::new(Requirement::parse(&v[1])?)))
+            }
+            ListNode::Identifier(ref s) if s == "library" && v.len() == 2 => {
+                Ok(Requirement::Library(parse_library_name(&v[1])?))
+            }
+            _ => Err("Invalid feature requirement.".to_string()),
+        }
+    }
+}
+
+impl ImportSet {
+    pub fn parse(n: &ListNode) -> Result<ImportSet, String> {
+        let v = match *n {
+            ListNode::Node(ref v) => v,
+            _ => return Err("An import set must be a list.".to_string()),
+        };
+        let modifier = match v.first() {
+            Some(ListNode::Identifier(s)) if v.len() >= 2 => {
+                match v[1] {
+                    ListNode::Node(..) => s.as_str(),
+                    _ => "",
+                }
+            }
+            _ => "",
+        };
+        let names = || v[2..].iter().map(identifier).collect::<Result<Vec<String>, String>>();
+        match modifier {
+            "only" => Ok(ImportSet::Only(Box::new(ImportSet::parse(&v[1])?), names()?)),
+            "except" => Ok(ImportSet::Except(Box::new(ImportSet::parse(&v[1])?), names()?)),
+            "prefix" => {
+                if v.len() != 3 {
+                    return Err("'prefix' requires an import set and a prefix.".to_string());
+                }
+                Ok(ImportSet::Prefix(Box::new(ImportSet::parse(&v[1])?), identifier(&v[2])?))
+            }
+            "rename" => {
+                let renames = v[2..].iter().map(parse_rename).collect::<Result<Vec<_>, _>>()?;
+                Ok(ImportSet::Rename(Box::new(ImportSet::parse(&v[1])?), renames))
+            }
+            _ => Ok(ImportSet::Library(parse_library_name(n)?)),
+        }
+    }
+}
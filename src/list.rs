@@ -1,50 +1,99 @@
 
 use parser::*;
-use std::vec::*;
+use numeric_vector::ElementType;
+use port::Port;
 
 #[derive(Debug,Clone)]
 pub enum ListNode {
-    Node(bool, Vec<ListNode>),
-    Identifier(bool, String),
+    Node(Vec<ListNode>),
+    Identifier(String),
     StringLiteral(String),
     NumericLiteral(f64),
     BooleanLiteral(bool),
+    CharLiteral(char),
+    Vector(Vec<ListNode>),
+    Bytevector(Vec<u8>),
+    NumericVector(ElementType, Vec<f64>),
 }
 
 impl ListNode {
-    pub fn from_primitive_tokens(mut it: &mut IntoIter<PrimitiveToken>, quoted: bool) -> ListNode {
+    /// Reads the next datum from the port, or returns `None` at the end of the input.
+    /// Afterwards, the port's `token_start` is where the datum started.
+    pub fn read(port: &mut Port) -> Result<Option<ListNode>, String> {
+        match next_token(port)? {
+            Some(token) => {
+                let start = port.token_start;
+                let datum = ListNode::from_token(token, port)?;
+                port.token_start = start;
+                Ok(Some(datum))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the elements of a list or a vector, up to the closing parenthesis.
+    fn read_items(port: &mut Port) -> Result<Vec<ListNode>, String> {
         let mut children: Vec<ListNode> = Vec::new();
-        let mut quote_next = false;
         loop {
-            let mut token_option = it.next();
-            if token_option.is_none() {
-                break;
+            match next_token(port)? {
+                Some(PrimitiveToken::RightParen) => return Ok(children),
+                Some(token) => children.push(ListNode::from_token(token, port)?),
+                None => return Err("Unexpected end of input inside a list.".to_string()),
             }
-            let token = token_option.take().unwrap();
-            match token {
-                PrimitiveToken::Word(s) => {
-                    match s.as_str() {
-                        "#t" => {
-                            children.push(ListNode::BooleanLiteral(true));
-                            quote_next = false
-                        }
-                        "#f" => {
-                            children.push(ListNode::BooleanLiteral(false));
-                            quote_next = false
+        }
+    }
+
+    /// Builds the datum starting with `token`, reading the rest of it from the port.
+    fn from_token(token: PrimitiveToken, port: &mut Port) -> Result<ListNode, String> {
+        match token {
+            PrimitiveToken::Word(s) => {
+                Ok(match s.as_str() {
+                    "#t" | "#true" => ListNode::BooleanLiteral(true),
+                    "#f" | "#false" => ListNode::BooleanLiteral(false),
+                    _ => ListNode::Identifier(s),
+                })
+            }
+            PrimitiveToken::StringLiteral(s) => Ok(ListNode::StringLiteral(s)),
+            PrimitiveToken::NumericLiteral(v) => Ok(ListNode::NumericLiteral(v)),
+            PrimitiveToken::CharLiteral(c) => Ok(ListNode::CharLiteral(c)),
+            PrimitiveToken::LeftParen => Ok(ListNode::Node(ListNode::read_items(port)?)),
+            PrimitiveToken::VectorParen => Ok(ListNode::Vector(ListNode::read_items(port)?)),
+            PrimitiveToken::BytevectorParen => {
+                let mut bytes = Vec::new();
+                for item in ListNode::read_items(port)? {
+                    match item {
+                        ListNode::NumericLiteral(v) if (0.0..=255.0).contains(&v) &&
+                                                       v.fract() == 0.0 => bytes.push(v as u8),
+                        _ => {
+                            return Err("A bytevector may only contain integers from 0 to 255."
+                                .to_string())
                         }
-                        _ => children.push(ListNode::Identifier(quote_next, s)),
                     }
                 }
-                PrimitiveToken::StringLiteral(s) => children.push(ListNode::StringLiteral(s)),
-                PrimitiveToken::NumericLiteral(v) => children.push(ListNode::NumericLiteral(v)),
-                PrimitiveToken::LeftParen => {
-                    children.push(ListNode::from_primitive_tokens(&mut it, quote_next));
-                    quote_next = false;
+                Ok(ListNode::Bytevector(bytes))
+            }
+            PrimitiveToken::NumericVectorParen(t) => {
+                let mut values = Vec::new();
+                for item in ListNode::read_items(port)? {
+                    match item {
+                        ListNode::NumericLiteral(v) if t.accepts(v) => values.push(v),
+                        _ => {
+                            return Err(format!("The elements of #{}(...) must each be {}.",
+                                               t.tag(),
+                                               t.description()))
+                        }
+                    }
                 }
-                PrimitiveToken::RightParen => break,
-                PrimitiveToken::Quote => quote_next = true,
+                Ok(ListNode::NumericVector(t, values))
+            }
+            PrimitiveToken::RightParen => Err("Unexpected ')'.".to_string()),
+            PrimitiveToken::Quote => {
+                let quoted = match next_token(port)? {
+                    Some(token) => ListNode::from_token(token, port)?,
+                    None => return Err("Unexpected end of input after a quote.".to_string()),
+                };
+                Ok(ListNode::Node(vec![ListNode::Identifier("quote".to_string()), quoted]))
             }
         }
-        ListNode::Node(quoted, children)
     }
 }
@@ -3,13 +3,25 @@ mod parser;
 mod list;
 mod expression;
 mod program;
+mod condition;
+mod environment;
+mod port;
+mod library;
+mod character;
+mod numeric_vector;
+mod hash_table;
 
 use std::fs::File;
 use std::io::prelude::*;
 use std::io;
 use std::env;
+use std::thread;
 use program::Program;
 
+/// Procedure calls don't use the Rust stack, but reading, printing and comparing deeply nested
+/// data still recurse, so give the interpreter plenty of room.
+const INTERPRETER_STACK_SIZE: usize = 512 * 1024 * 1024;
+
 fn get_file_list() -> Vec<String> {
     let mut args = env::args();
     args.next(); // Skip the program name.
@@ -20,7 +32,29 @@ fn get_file_list() -> Vec<String> {
     files
 }
 
+fn read_file(path: &str) -> Option<String> {
+    match File::open(path) {
+        Ok(mut file) => {
+            let mut code = String::new();
+            file.read_to_string(&mut code).unwrap();
+            Some(code)
+        }
+        Err(_) => {
+            println!("Failed to open file {}.", path);
+            None
+        }
+    }
+}
+
 fn main() {
+    let interpreter = thread::Builder::new()
+        .stack_size(INTERPRETER_STACK_SIZE)
+        .spawn(run_interpreter)
+        .unwrap();
+    interpreter.join().unwrap();
+}
+
+fn run_interpreter() {
     println!("rScheme - A minimal Scheme intepreter written in Rust (v0.0.1 Alpha)");
     println!("Copyright (C) 2016 School of Engineering - Aristotle University of Thessaloniki");
     println!("---------------");
@@ -28,17 +62,11 @@ fn main() {
     println!("Loading the standard library...");
 
     let mut program = Program::new();
-    let mut files = vec!["stdlib.scm".to_string()];
-    files.append(&mut get_file_list());
-    for path in files {
-        match File::open(path.clone()) {
-            Ok(mut file) => {
-                let mut code = String::new();
-                file.read_to_string(&mut code).unwrap();
-                program.run_code(code, true);
-            }
-            Err(_) => println!("Failed to open file {}.", path),
-        }
+    if let Some(code) = read_file("stdlib.scm") {
+        program.run_prelude(code);
+    }
+    for path in get_file_list() {
+        program.run_file(&path);
     }
 
     println!("---------------");
@@ -46,12 +74,12 @@ fn main() {
         print!("]=> ");
         io::stdout().flush().unwrap();
         let mut accum = String::new();
-        io::stdin().read_line(&mut accum).unwrap();
-        if accum.trim() == "exit" {
+        let read = io::stdin().read_line(&mut accum).unwrap();
+        if read == 0 || accum.trim() == "exit" {
             println!("Goodbye!");
             break;
         }
         program.run_code(accum, false);
-        println!("");
+        println!();
     }
 }
@@ -0,0 +1,291 @@
+use expression::Boxed;
+use std::rc::Rc;
+
+/// The element type of a homogeneous numeric vector (SRFI 4).
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum ElementType {
+    U8,
+    S8,
+    U16,
+    S16,
+    U32,
+    S32,
+    U64,
+    S64,
+    F32,
+    F64,
+}
+
+/// The procedures defined for every element type, as in `f64vector-ref`.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum NumericVectorOp {
+    IsVector,
+    Make,
+    Construct,
+    Length,
+    Ref,
+    Set,
+    ToList,
+    FromList,
+    ToVector,
+    FromVector,
+}
+
+macro_rules! procedure_names {
+    ($tag:literal) => {
+        [concat!($tag, "vector?"),
+         concat!("make-", $tag, "vector"),
+         concat!($tag, "vector"),
+         concat!($tag, "vector-length"),
+         concat!($tag, "vector-ref"),
+         concat!($tag, "vector-set!"),
+         concat!($tag, "vector->list"),
+         concat!("list->", $tag, "vector"),
+         concat!($tag, "vector->vector"),
+         concat!("vector->", $tag, "vector")]
+    };
+}
+
+/// The names of the procedures, indexed by element type and then by operation.
+const PROCEDURE_NAMES: [[&str; 10]; 10] = [procedure_names!("u8"),
+                                           procedure_names!("s8"),
+                                           procedure_names!("u16"),
+                                           procedure_names!("s16"),
+                                           procedure_names!("u32"),
+                                           procedure_names!("s32"),
+                                           procedure_names!("u64"),
+                                           procedure_names!("s64"),
+                                           procedure_names!("f32"),
+                                           procedure_names!("f64")];
+
+impl ElementType {
+    pub const ALL: [ElementType; 10] = [ElementType::U8,
+                                        ElementType::S8,
+                                        ElementType::U16,
+                                        ElementType::S16,
+                                        ElementType::U32,
+                                        ElementType::S32,
+                                        ElementType::U64,
+                                        ElementType::S64,
+                                        ElementType::F32,
+                                        ElementType::F64];
+
+    /// The tag used in the names of the procedures and in literals such as `#f64(...)`.
+    pub fn tag(self) -> &'static str {
+        match self {
+            ElementType::U8 => "u8",
+            ElementType::S8 => "s8",
+            ElementType::U16 => "u16",
+            ElementType::S16 => "s16",
+            ElementType::U32 => "u32",
+            ElementType::S32 => "s32",
+            ElementType::U64 => "u64",
+            ElementType::S64 => "s64",
+            ElementType::F32 => "f32",
+            ElementType::F64 => "f64",
+        }
+    }
+
+    pub fn from_tag(tag: &str) -> Option<ElementType> {
+        ElementType::ALL.iter().cloned().find(|t| t.tag() == tag)
+    }
+
+    /// Describes the numbers a vector of this type can hold, for error messages.
+    pub fn description(self) -> &'static str {
+        match self {
+            ElementType::U8 => "an integer from 0 to 255",
+            ElementType::S8 => "an integer from -128 to 127",
+            ElementType::U16 => "an integer from 0 to 65535",
+            ElementType::S16 => "an integer from -32768 to 32767",
+            ElementType::U32 => "an integer from 0 to 4294967295",
+            ElementType::S32 => "an integer from -2147483648 to 2147483647",
+            ElementType::U64 => "an integer from 0 to 2^64 - 1",
+            ElementType::S64 => "an integer from -2^63 to 2^63 - 1",
+            ElementType::F32 | ElementType::F64 => "a number",
+        }
+    }
+
+    /// Whether `x` can be stored in a vector of this type without changing its value, as
+    /// far as an `f64` can tell.
+    pub fn accepts(self, x: f64) -> bool {
+        let (min, max) = match self {
+            ElementType::U8 => (0.0, 256.0),
+            ElementType::S8 => (-128.0, 128.0),
+            ElementType::U16 => (0.0, 65536.0),
+            ElementType::S16 => (-32768.0, 32768.0),
+            ElementType::U32 => (0.0, 4294967296.0),
+            ElementType::S32 => (-2147483648.0, 2147483648.0),
+            ElementType::U64 => (0.0, 2f64.powi(64)),
+            ElementType::S64 => (-2f64.powi(63), 2f64.powi(63)),
+            ElementType::F32 | ElementType::F64 => return true,
+        };
+        x.fract() == 0.0 && x >= min && x < max
+    }
+}
+
+impl NumericVectorOp {
+    pub const ALL: [NumericVectorOp; 10] = [NumericVectorOp::IsVector,
+                                            NumericVectorOp::Make,
+                                            NumericVectorOp::Construct,
+                                            NumericVectorOp::Length,
+                                            NumericVectorOp::Ref,
+                                            NumericVectorOp::Set,
+                                            NumericVectorOp::ToList,
+                                            NumericVectorOp::FromList,
+                                            NumericVectorOp::ToVector,
+                                            NumericVectorOp::FromVector];
+}
+
+pub fn procedure_name(t: ElementType, op: NumericVectorOp) -> &'static str {
+    PROCEDURE_NAMES[t as usize][op as usize]
+}
+
+/// A homogeneous numeric vector, holding its elements unboxed. The u8 vectors are
+/// bytevectors, so they have no variant here.
+#[derive(Debug,Clone,PartialEq)]
+pub enum NumericVector {
+    S8(Vec<i8>),
+    U16(Vec<u16>),
+    S16(Vec<i16>),
+    U32(Vec<u32>),
+    S32(Vec<i32>),
+    U64(Vec<u64>),
+    S64(Vec<i64>),
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+}
+
+/// Evaluates `$body` with `$items` bound to the elements of the vector, whatever their type.
+macro_rules! with_items {
+    ($vector:expr, $items:ident => $body:expr) => {
+        match $vector {
+            NumericVector::S8($items) => $body,
+            NumericVector::U16($items) => $body,
+            NumericVector::S16($items) => $body,
+            NumericVector::U32($items) => $body,
+            NumericVector::S32($items) => $body,
+            NumericVector::U64($items) => $body,
+            NumericVector::S64($items) => $body,
+            NumericVector::F32($items) => $body,
+            NumericVector::F64($items) => $body,
+        }
+    };
+}
+
+impl NumericVector {
+    /// Makes a vector of type `t` out of values it accepts. `t` can't be `U8`.
+    pub fn new(t: ElementType, values: &[f64]) -> NumericVector {
+        match t {
+            ElementType::U8 => panic!("u8 vectors are bytevectors"),
+            ElementType::S8 => NumericVector::S8(values.iter().map(|&x| x as i8).collect()),
+            ElementType::U16 => NumericVector::U16(values.iter().map(|&x| x as u16).collect()),
+            ElementType::S16 => NumericVector::S16(values.iter().map(|&x| x as i16).collect()),
+            ElementType::U32 => NumericVector::U32(values.iter().map(|&x| x as u32).collect()),
+            ElementType::S32 => NumericVector::S32(values.iter().map(|&x| x as i32).collect()),
+            ElementType::U64 => NumericVector::U64(values.iter().map(|&x| x as u64).collect()),
+            ElementType::S64 => NumericVector::S64(values.iter().map(|&x| x as i64).collect()),
+            ElementType::F32 => NumericVector::F32(values.iter().map(|&x| x as f32).collect()),
+            ElementType::F64 => NumericVector::F64(values.to_vec()),
+        }
+    }
+
+    pub fn element_type(&self) -> ElementType {
+        match *self {
+            NumericVector::S8(_) => ElementType::S8,
+            NumericVector::U16(_) => ElementType::U16,
+            NumericVector::S16(_) => ElementType::S16,
+            NumericVector::U32(_) => ElementType::U32,
+            NumericVector::S32(_) => ElementType::S32,
+            NumericVector::U64(_) => ElementType::U64,
+            NumericVector::S64(_) => ElementType::S64,
+            NumericVector::F32(_) => ElementType::F32,
+            NumericVector::F64(_) => ElementType::F64,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        with_items!(self, items => items.len())
+    }
+
+    pub fn get(&self, k: usize) -> f64 {
+        match *self {
+            NumericVector::S8(ref items) => items[k] as f64,
+            NumericVector::U16(ref items) => items[k] as f64,
+            NumericVector::S16(ref items) => items[k] as f64,
+            NumericVector::U32(ref items) => items[k] as f64,
+            NumericVector::S32(ref items) => items[k] as f64,
+            NumericVector::U64(ref items) => items[k] as f64,
+            NumericVector::S64(ref items) => items[k] as f64,
+            NumericVector::F32(ref items) => items[k] as f64,
+            NumericVector::F64(ref items) => items[k],
+        }
+    }
+
+    /// Stores `x`, which must be accepted by the vector's element type, at index `k`.
+    pub fn set(&mut self, k: usize, x: f64) {
+        with_items!(self, items => items[k] = x as _)
+    }
+
+    /// The elements as `write` shows them, each in its own precision.
+    pub fn format_items(&self) -> Vec<String> {
+        with_items!(self, items => items.iter().map(|x| x.to_string()).collect())
+    }
+
+    pub fn to_f64s(&self) -> Vec<f64> {
+        (0..self.len()).map(|k| self.get(k)).collect()
+    }
+
+    /// Whether both vectors have the same type and their elements are `eqv?`: a NaN is the
+    /// same as any other NaN, but 0 and -0 differ.
+    pub fn eqv_elements(&self, other: &NumericVector) -> bool {
+        fn same_float(x: f64, y: f64) -> bool {
+            x.to_bits() == y.to_bits() || (x.is_nan() && y.is_nan())
+        }
+        match (self, other) {
+            (NumericVector::F32(a), NumericVector::F32(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(&x, &y)| same_float(x as f64, y as f64))
+            }
+            (NumericVector::F64(a), NumericVector::F64(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(&x, &y)| same_float(x, y))
+            }
+            _ => self == other,
+        }
+    }
+}
+
+/// A homogeneous vector of any element type, as given to one of its procedures.
+pub enum TypedVector {
+    Bytes(Rc<Boxed<Vec<u8>>>),
+    Numbers(Rc<Boxed<NumericVector>>),
+}
+
+impl TypedVector {
+    pub fn len(&self) -> usize {
+        match *self {
+            TypedVector::Bytes(ref v) => v.borrow().len(),
+            TypedVector::Numbers(ref v) => v.borrow().len(),
+        }
+    }
+
+    pub fn get(&self, k: usize) -> f64 {
+        match *self {
+            TypedVector::Bytes(ref v) => v.borrow()[k] as f64,
+            TypedVector::Numbers(ref v) => v.borrow().get(k),
+        }
+    }
+
+    /// Stores `x`, which must be accepted by the vector's element type, at index `k`.
+    pub fn set(&self, k: usize, x: f64) {
+        match *self {
+            TypedVector::Bytes(ref v) => v.borrow_mut()[k] = x as u8,
+            TypedVector::Numbers(ref v) => v.borrow_mut().set(k, x),
+        }
+    }
+
+    pub fn to_f64s(&self) -> Vec<f64> {
+        match *self {
+            TypedVector::Bytes(ref v) => v.borrow().iter().map(|&b| b as f64).collect(),
+            TypedVector::Numbers(ref v) => v.borrow().to_f64s(),
+        }
+    }
+}
@@ -1,74 +1,185 @@
+use character::parse_char;
+use numeric_vector::ElementType;
+use port::Port;
+use std::f64;
 
 #[derive(Debug)]
 #[derive(Clone)]
 pub enum PrimitiveToken {
     LeftParen,
+    /// The `#(` that starts a vector.
+    VectorParen,
+    /// The `#u8(` that starts a bytevector.
+    BytevectorParen,
+    /// The `#f64(` or similar that starts a homogeneous numeric vector of another type.
+    NumericVectorParen(ElementType),
     RightParen,
     Word(String),
     StringLiteral(String),
     NumericLiteral(f64),
+    CharLiteral(char),
     Quote,
 }
 
-fn split_word(accum: &mut String, tokens: &mut Vec<PrimitiveToken>) {
-    if !accum.is_empty() {
-        match accum.to_string().parse::<f64>() {
-            Ok(v) => tokens.push(PrimitiveToken::NumericLiteral(v)),
-            Err(_) => tokens.push(PrimitiveToken::Word(accum.to_string())),
-        };
-        *accum = String::new();
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || "()[]\";'".contains(c)
+}
+
+fn split_word(word: String, fold_case: bool) -> PrimitiveToken {
+    match word.as_str() {
+        "+inf.0" => return PrimitiveToken::NumericLiteral(f64::INFINITY),
+        "-inf.0" => return PrimitiveToken::NumericLiteral(f64::NEG_INFINITY),
+        "+nan.0" | "-nan.0" => return PrimitiveToken::NumericLiteral(f64::NAN),
+        _ => {}
+    }
+    // Rust also accepts names like `inf` and `nan`, which are symbols in Scheme.
+    if word.chars().any(|c| c.is_ascii_digit()) {
+        if let Ok(v) = word.parse::<f64>() {
+            return PrimitiveToken::NumericLiteral(v);
+        }
+    }
+    if fold_case {
+        PrimitiveToken::Word(word.to_lowercase())
+    } else {
+        PrimitiveToken::Word(word)
     }
 }
 
-pub fn parse_primitives(code: &String) -> Vec<PrimitiveToken> {
-    let mut tokens: Vec<PrimitiveToken> = Vec::new();
-    // Parser state machine
-    let mut word_accumulator: String = String::new();
-    let mut in_comment = false;
-    let mut in_string = false;
-    for c in code.chars() {
-        if in_comment {
-            if c == '\n' {
-                in_comment = false;
+fn is_intraline_whitespace(c: char) -> bool {
+    c.is_whitespace() && c != '\n'
+}
+
+/// Reads what follows a backslash in a string literal, adding the character it stands for
+/// to `literal`. A backslash at the end of a line joins it to the next one, skipping the
+/// indentation around the line break.
+fn read_escape(port: &mut Port, literal: &mut String) -> Result<(), String> {
+    let c = match port.read_char()? {
+        Some(c) => c,
+        None => return Err("Unterminated string literal.".to_string()),
+    };
+    match c {
+        'a' => literal.push('\u{7}'),
+        'b' => literal.push('\u{8}'),
+        't' => literal.push('\t'),
+        'n' => literal.push('\n'),
+        'r' => literal.push('\r'),
+        '"' | '\\' | '|' => literal.push(c),
+        'x' | 'X' => {
+            let mut digits = String::new();
+            loop {
+                match port.read_char()? {
+                    Some(';') => break,
+                    Some(c) if c.is_ascii_hexdigit() => digits.push(c),
+                    _ => return Err("A hex escape in a string must end with ';'.".to_string()),
+                }
             }
-        } else if in_string {
-            if c == '\"' {
-                in_string = false;
-                tokens.push(PrimitiveToken::StringLiteral(word_accumulator));
-                word_accumulator = String::new();
-            } else {
-                word_accumulator.push(c);
+            match u32::from_str_radix(&digits, 16).ok().and_then(::std::char::from_u32) {
+                Some(c) => literal.push(c),
+                None => return Err(format!("Invalid hex escape \\x{}; in a string.", digits)),
             }
-        } else {
-            match c {
-                '(' | '[' => {
-                    split_word(&mut word_accumulator, &mut tokens);
-                    tokens.push(PrimitiveToken::LeftParen)
+        }
+        c if c.is_whitespace() => {
+            let mut c = c;
+            while c != '\n' {
+                c = match port.read_char()? {
+                    Some(c) if c.is_whitespace() => c,
+                    _ => return Err("Expected a line break after '\\' in a string.".to_string()),
+                };
+            }
+            while let Some(c) = port.peek_char()? {
+                if !is_intraline_whitespace(c) {
+                    break;
                 }
+                port.read_char()?;
+            }
+        }
+        c => return Err(format!("Unknown escape sequence \\{} in a string.", c)),
+    }
+    Ok(())
+}
 
-                ')' | ']' => {
-                    split_word(&mut word_accumulator, &mut tokens);
-                    tokens.push(PrimitiveToken::RightParen)
+/// Reads the next token from the port, consuming no more characters than it needs. Returns
+/// `None` at the end of the input.
+pub fn next_token(port: &mut Port) -> Result<Option<PrimitiveToken>, String> {
+    loop {
+        port.token_start = port.position();
+        let c = match port.read_char()? {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+        match c {
+            '(' | '[' => return Ok(Some(PrimitiveToken::LeftParen)),
+            ')' | ']' => return Ok(Some(PrimitiveToken::RightParen)),
+            ';' => {
+                while let Some(c) = port.read_char()? {
+                    if c == '\n' {
+                        break;
+                    }
                 }
-
-                ' ' | '\n' | '\t' | '\r' => split_word(&mut word_accumulator, &mut tokens),
-                ';' => {
-                    split_word(&mut word_accumulator, &mut tokens);
-                    in_comment = true
+            }
+            '\"' => {
+                let mut literal = String::new();
+                loop {
+                    match port.read_char()? {
+                        Some('\"') => return Ok(Some(PrimitiveToken::StringLiteral(literal))),
+                        Some('\\') => read_escape(port, &mut literal)?,
+                        Some(c) => literal.push(c),
+                        None => return Err("Unterminated string literal.".to_string()),
+                    }
                 }
-
-                '\"' => {
-                    split_word(&mut word_accumulator, &mut tokens);
-                    in_string = true
+            }
+            '\'' => return Ok(Some(PrimitiveToken::Quote)),
+            c if c.is_whitespace() => {}
+            '#' if port.peek_char()? == Some('(') => {
+                port.read_char()?;
+                return Ok(Some(PrimitiveToken::VectorParen));
+            }
+            '#' if port.peek_char()? == Some('\\') => {
+                port.read_char()?;
+                // The character right after the backslash is taken even if it's a delimiter,
+                // as in `#\(` or `#\ `.
+                let mut text = match port.read_char()? {
+                    Some(c) => c.to_string(),
+                    None => return Err("Unexpected end of input in a character.".to_string()),
+                };
+                while let Some(c) = port.peek_char()? {
+                    if is_delimiter(c) {
+                        break;
+                    }
+                    text.push(c);
+                    port.read_char()?;
                 }
-
-                '\'' => {
-                    split_word(&mut word_accumulator, &mut tokens);
-                    tokens.push(PrimitiveToken::Quote)
+                return match parse_char(&text) {
+                    Some(c) => Ok(Some(PrimitiveToken::CharLiteral(c))),
+                    None => Err(format!("Unknown character #\\{}.", text)),
+                };
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(c) = port.peek_char()? {
+                    if is_delimiter(c) {
+                        break;
+                    }
+                    word.push(c);
+                    port.read_char()?;
+                }
+                match word.as_str() {
+                    "#u8" | "#U8" if port.peek_char()? == Some('(') => {
+                        port.read_char()?;
+                        return Ok(Some(PrimitiveToken::BytevectorParen));
+                    }
+                    _ if word.starts_with('#') && port.peek_char()? == Some('(') => {
+                        if let Some(t) = ElementType::from_tag(&word[1..].to_lowercase()) {
+                            port.read_char()?;
+                            return Ok(Some(PrimitiveToken::NumericVectorParen(t)));
+                        }
+                        return Ok(Some(split_word(word, port.fold_case)));
+                    }
+                    "#!fold-case" => port.fold_case = true,
+                    "#!no-fold-case" => port.fold_case = false,
+                    _ => return Ok(Some(split_word(word, port.fold_case))),
                 }
-                _ => word_accumulator.push(c),
             }
         }
     }
-    tokens
 }
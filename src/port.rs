@@ -0,0 +1,420 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fmt::Formatter;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+
+/// A position in the input of a port. Lines and columns count from 1.
+#[derive(Debug,Clone,Copy)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Where the characters written to an output port end up.
+pub enum Sink {
+    Stdout,
+    Stderr,
+    File(BufWriter<File>),
+    String(String),
+    Bytes(Vec<u8>),
+}
+
+/// Where the characters read from an input port come from.
+pub enum Reader {
+    Stdin,
+    Buffered(Box<dyn BufRead>),
+    /// The whole input is already pending, as for string ports.
+    Memory,
+}
+
+/// The input side of a port. Characters are read a line at a time and kept in `pending`
+/// until they're consumed.
+pub struct Source {
+    reader: Reader,
+    pending: VecDeque<char>,
+    /// The position of the next character.
+    position: Position,
+}
+
+impl Source {
+    /// Makes sure there's a character pending, unless the end of the input was reached.
+    fn fill(&mut self) -> Result<(), String> {
+        if !self.pending.is_empty() {
+            return Ok(());
+        }
+        let mut line = String::new();
+        let result = match self.reader {
+            Reader::Stdin => io::stdin().read_line(&mut line),
+            Reader::Buffered(ref mut reader) => reader.read_line(&mut line),
+            Reader::Memory => return Ok(()),
+        };
+        result.map_err(|e| format!("Failed to read from the port: {}.", e))?;
+        self.pending.extend(line.chars());
+        Ok(())
+    }
+
+    fn new(reader: Reader, pending: VecDeque<char>) -> Source {
+        Source {
+            reader,
+            pending,
+            position: Position { line: 1, column: 1 },
+        }
+    }
+}
+
+/// The input side of a binary port. Bytes are read a buffer at a time and kept in `pending`
+/// until they're consumed.
+pub struct BinarySource {
+    /// `None` when the whole input is already pending, as for bytevector ports.
+    reader: Option<Box<dyn BufRead>>,
+    pending: VecDeque<u8>,
+}
+
+impl BinarySource {
+    /// Makes sure there's a byte pending, unless the end of the input was reached.
+    fn fill(&mut self) -> Result<(), String> {
+        if !self.pending.is_empty() {
+            return Ok(());
+        }
+        if let Some(ref mut reader) = self.reader {
+            let count = {
+                let buffer = reader.fill_buf()
+                    .map_err(|e| format!("Failed to read from the port: {}.", e))?;
+                self.pending.extend(buffer.iter());
+                buffer.len()
+            };
+            reader.consume(count);
+        }
+        Ok(())
+    }
+}
+
+/// A port, as returned by `current-output-port`, `open-input-file` and friends.
+pub struct Port {
+    /// The name of the file the port was opened on, if any.
+    pub name: Option<String>,
+    pub input: Option<Source>,
+    pub binary_input: Option<BinarySource>,
+    pub output: Option<Sink>,
+    /// Whether the port reads or writes bytes rather than characters.
+    pub binary: bool,
+    pub open: bool,
+    /// Whether the last character written was a newline, or nothing was written yet.
+    pub line_start: bool,
+    /// Whether the reader folds identifiers to lower case, as set by `#!fold-case`.
+    pub fold_case: bool,
+    /// Where the last token read from the port started.
+    pub token_start: Position,
+}
+
+impl Port {
+    fn new(input: Option<Source>, output: Option<Sink>) -> Port {
+        Port {
+            name: None,
+            input,
+            binary_input: None,
+            output,
+            binary: false,
+            open: true,
+            line_start: true,
+            fold_case: false,
+            token_start: Position { line: 1, column: 1 },
+        }
+    }
+
+    pub fn console() -> Port {
+        Port::new(None, Some(Sink::Stdout))
+    }
+
+    pub fn console_error() -> Port {
+        Port::new(None, Some(Sink::Stderr))
+    }
+
+    pub fn console_input() -> Port {
+        Port::new(Some(Source::new(Reader::Stdin, VecDeque::new())), None)
+    }
+
+    pub fn open_input_file(path: &str) -> io::Result<Port> {
+        let file = File::open(path)?;
+        let reader = Reader::Buffered(Box::new(BufReader::new(file)));
+        let mut port = Port::new(Some(Source::new(reader, VecDeque::new())), None);
+        port.name = Some(path.to_string());
+        Ok(port)
+    }
+
+    pub fn open_output_file(path: &str) -> io::Result<Port> {
+        let file = File::create(path)?;
+        let mut port = Port::new(None, Some(Sink::File(BufWriter::new(file))));
+        port.name = Some(path.to_string());
+        Ok(port)
+    }
+
+    pub fn open_input_string(text: &str) -> Port {
+        Port::new(Some(Source::new(Reader::Memory, text.chars().collect())), None)
+    }
+
+    pub fn open_output_string() -> Port {
+        Port::new(None, Some(Sink::String(String::new())))
+    }
+
+    pub fn open_binary_input_file(path: &str) -> io::Result<Port> {
+        let file = File::open(path)?;
+        let mut port = Port::binary_input(Some(Box::new(BufReader::new(file))), VecDeque::new());
+        port.name = Some(path.to_string());
+        Ok(port)
+    }
+
+    pub fn open_binary_output_file(path: &str) -> io::Result<Port> {
+        let mut port = Port::open_output_file(path)?;
+        port.binary = true;
+        Ok(port)
+    }
+
+    pub fn open_input_bytevector(bytes: &[u8]) -> Port {
+        Port::binary_input(None, bytes.iter().cloned().collect())
+    }
+
+    pub fn open_output_bytevector() -> Port {
+        let mut port = Port::new(None, Some(Sink::Bytes(Vec::new())));
+        port.binary = true;
+        port
+    }
+
+    fn binary_input(reader: Option<Box<dyn BufRead>>, pending: VecDeque<u8>) -> Port {
+        let mut port = Port::new(None, None);
+        port.binary_input = Some(BinarySource { reader, pending });
+        port.binary = true;
+        port
+    }
+
+    pub fn is_input(&self) -> bool {
+        self.input.is_some() || self.binary_input.is_some()
+    }
+
+    pub fn is_output(&self) -> bool {
+        self.output.is_some()
+    }
+
+    /// Returns the bytes written so far to a bytevector output port.
+    pub fn output_bytes(&self) -> Option<Vec<u8>> {
+        match self.output {
+            Some(Sink::Bytes(ref bytes)) => Some(bytes.clone()),
+            _ => None,
+        }
+    }
+
+    /// Returns the characters written so far to a string output port.
+    pub fn output_string(&self) -> Option<String> {
+        match self.output {
+            Some(Sink::String(ref s)) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn write_str(&mut self, text: &str) -> Result<(), String> {
+        if !self.open {
+            return Err("Can't write to a closed port.".to_string());
+        }
+        let result = match self.output {
+            Some(Sink::Stdout) => io::stdout().write_all(text.as_bytes()),
+            Some(Sink::Stderr) => io::stderr().write_all(text.as_bytes()),
+            Some(Sink::File(ref mut file)) => file.write_all(text.as_bytes()),
+            Some(Sink::String(ref mut s)) => {
+                s.push_str(text);
+                Ok(())
+            }
+            Some(Sink::Bytes(_)) => return Err("Expected a textual output port.".to_string()),
+            None => return Err("Expected an output port.".to_string()),
+        };
+        result.map_err(|e| format!("Failed to write to the port: {}.", e))?;
+        if let Some(c) = text.chars().last() {
+            self.line_start = c == '\n';
+        }
+        Ok(())
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), String> {
+        if !self.open {
+            return Err("Can't write to a closed port.".to_string());
+        }
+        let result = match self.output {
+            Some(Sink::File(ref mut file)) if self.binary => file.write_all(bytes),
+            Some(Sink::Bytes(ref mut b)) => {
+                b.extend_from_slice(bytes);
+                Ok(())
+            }
+            _ => return Err("Expected a binary output port.".to_string()),
+        };
+        result.map_err(|e| format!("Failed to write to the port: {}.", e))
+    }
+
+    pub fn flush(&mut self) -> Result<(), String> {
+        if !self.open {
+            return Err("Can't flush a closed port.".to_string());
+        }
+        let result = match self.output {
+            Some(Sink::Stdout) => io::stdout().flush(),
+            Some(Sink::Stderr) => io::stderr().flush(),
+            Some(Sink::File(ref mut file)) => file.flush(),
+            Some(Sink::String(_)) | Some(Sink::Bytes(_)) => Ok(()),
+            None => return Err("Expected an output port.".to_string()),
+        };
+        result.map_err(|e| format!("Failed to flush the port: {}.", e))
+    }
+
+    /// Closes the port, flushing any output that is still buffered. Closing a port twice
+    /// has no effect.
+    pub fn close(&mut self) -> Result<(), String> {
+        if !self.open {
+            return Ok(());
+        }
+        let result = if self.output.is_some() {
+            self.flush()
+        } else {
+            Ok(())
+        };
+        self.open = false;
+        result
+    }
+
+    fn source(&mut self) -> Result<&mut Source, String> {
+        if !self.open {
+            return Err("Can't read from a closed port.".to_string());
+        }
+        match self.input {
+            Some(ref mut source) => Ok(source),
+            None => Err("Expected an input port.".to_string()),
+        }
+    }
+
+    fn binary_source(&mut self) -> Result<&mut BinarySource, String> {
+        if !self.open {
+            return Err("Can't read from a closed port.".to_string());
+        }
+        match self.binary_input {
+            Some(ref mut source) => Ok(source),
+            None => Err("Expected a binary input port.".to_string()),
+        }
+    }
+
+    /// Returns the next byte without consuming it, or `None` at the end of the input.
+    pub fn peek_u8(&mut self) -> Result<Option<u8>, String> {
+        let source = self.binary_source()?;
+        source.fill()?;
+        Ok(source.pending.front().cloned())
+    }
+
+    pub fn read_u8(&mut self) -> Result<Option<u8>, String> {
+        let source = self.binary_source()?;
+        source.fill()?;
+        Ok(source.pending.pop_front())
+    }
+
+    /// Reads up to `k` bytes, returning `None` if the end of the input comes first.
+    pub fn read_bytes(&mut self, k: usize) -> Result<Option<Vec<u8>>, String> {
+        let mut bytes = Vec::new();
+        while bytes.len() < k {
+            match self.read_u8()? {
+                Some(b) => bytes.push(b),
+                None if bytes.is_empty() => return Ok(None),
+                None => break,
+            }
+        }
+        Ok(Some(bytes))
+    }
+
+    /// Whether a byte can be read without blocking. Binary ports only read from memory and
+    /// files, so it always can.
+    pub fn u8_ready(&mut self) -> Result<bool, String> {
+        self.binary_source().map(|_| true)
+    }
+
+    /// Returns the next character without consuming it, or `None` at the end of the input.
+    pub fn peek_char(&mut self) -> Result<Option<char>, String> {
+        let source = self.source()?;
+        source.fill()?;
+        Ok(source.pending.front().cloned())
+    }
+
+    pub fn read_char(&mut self) -> Result<Option<char>, String> {
+        let source = self.source()?;
+        source.fill()?;
+        let c = source.pending.pop_front();
+        match c {
+            Some('\n') => {
+                source.position.line += 1;
+                source.position.column = 1;
+            }
+            Some(_) => source.position.column += 1,
+            None => {}
+        }
+        Ok(c)
+    }
+
+    /// Returns the position of the next character to be read.
+    pub fn position(&self) -> Position {
+        match self.input {
+            Some(ref source) => source.position,
+            None => Position { line: 1, column: 1 },
+        }
+    }
+
+    /// Describes where the next character is, if the port was opened on a file.
+    pub fn location(&self) -> Option<String> {
+        self.name.as_ref().map(|name| format!("{}:{}", name, self.position()))
+    }
+
+    /// Reads the rest of the current line, without the line ending.
+    pub fn read_line(&mut self) -> Result<Option<String>, String> {
+        let mut line = String::new();
+        loop {
+            match self.read_char()? {
+                Some('\n') => break,
+                Some(c) => line.push(c),
+                None if line.is_empty() => return Ok(None),
+                None => break,
+            }
+        }
+        if line.ends_with('\r') {
+            line.pop();
+        }
+        Ok(Some(line))
+    }
+
+    /// Reads up to `k` characters, returning `None` if the end of the input comes first.
+    pub fn read_string(&mut self, k: usize) -> Result<Option<String>, String> {
+        let mut text = String::new();
+        for _ in 0..k {
+            match self.read_char()? {
+                Some(c) => text.push(c),
+                None if text.is_empty() => return Ok(None),
+                None => break,
+            }
+        }
+        Ok(Some(text))
+    }
+
+    /// Whether a character can be read without waiting for the user to type it.
+    pub fn char_ready(&mut self) -> Result<bool, String> {
+        let source = self.source()?;
+        Ok(match source.reader {
+            Reader::Stdin => !source.pending.is_empty(),
+            Reader::Buffered(_) | Reader::Memory => true,
+        })
+    }
+}
+
+impl fmt::Debug for Port {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "#<port>")
+    }
+}
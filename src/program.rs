@@ -1,314 +1,2849 @@
 
 use std::collections::HashMap;
-use parser;
+use std::io;
+use std::io::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
 use list::ListNode;
 use expression::*;
+use condition::*;
+use environment::*;
+use port::{Port, Position};
+use library::*;
+use character;
+use numeric_vector::*;
+use hash_table::*;
+use std::env;
+use std::path::{Path, PathBuf};
 use std::cmp::Ordering;
+use std::iter;
+use std::mem;
+use std::fmt;
+use std::fmt::Formatter;
+
+/// A file being loaded, and the position of the top-level form being evaluated from it.
+struct SourceFile {
+    path: PathBuf,
+    position: Position,
+}
+
+/// The next thing for the evaluator to do.
+enum Step {
+    Eval(Rc<Expression>, Environment),
+    /// Hands a value to the innermost pending frame.
+    Return(LValue),
+    Call(Procedure, Vec<LValue>),
+}
+
+/// A form or call waiting for the value of an inner expression or call, along with the
+/// dynamic state to restore when it's left.
+#[derive(Clone)]
+enum Pending {
+    /// The operator and operands of a call, the first `values.len()` of which have been
+    /// evaluated.
+    Arguments {
+        children: Body,
+        environment: Environment,
+        values: Vec<LValue>,
+    },
+    /// A body whose expression at `index` is being evaluated. The frame is gone by the time
+    /// the last expression is evaluated, so that it's in tail position.
+    Sequence {
+        body: Body,
+        index: usize,
+        environment: Environment,
+    },
+    Definition {
+        name: String,
+        environment: Environment,
+    },
+    DefineValues {
+        formals: Formals,
+        environment: Environment,
+    },
+    IfCondition {
+        yes_expr: Rc<Expression>,
+        no_expr: Option<Rc<Expression>>,
+        environment: Environment,
+    },
+    /// The body of a `restart-case`, whose restarts have the given ids and are above `depth`.
+    RestartCase {
+        clauses: Rc<[RestartClause]>,
+        ids: Vec<usize>,
+        depth: usize,
+        environment: Environment,
+    },
+    /// The initializer at `index` of a `let-values`. The values of the previous ones are
+    /// bound in `frame`, or in `environment` if the bindings are sequential.
+    LetValues {
+        bindings: Rc<[(Formals, Rc<Expression>)]>,
+        body: Body,
+        sequential: bool,
+        index: usize,
+        frame: HashMap<String, LValue>,
+        environment: Environment,
+    },
+    /// The binding of a `parameterize` that follows the ones whose values are known: its
+    /// parameter, or its value once the parameter is known.
+    Parameterize {
+        bindings: Rc<[(Rc<Expression>, Rc<Expression>)]>,
+        body: Body,
+        parameter: Option<Rc<Parameter>>,
+        values: Vec<(Rc<Parameter>, LValue)>,
+        environment: Environment,
+    },
+    /// The body of a `parameterize`, whose parameterizations are above the given depth.
+    Parameterized(usize),
+    /// An expression in which an error was signalled, with its `use-value` restart, which is
+    /// above `depth`.
+    UseValue { id: usize, depth: usize },
+    /// A handler being called, which is put back in effect when it's left.
+    Handler(Procedure),
+    /// A handler called for a non-continuable raise, which must not return.
+    NonContinuable,
+    /// The thunk of a `with-exception-handler`, whose handler is above the given depth.
+    WithHandler(usize),
+    /// The before thunk of a `dynamic-wind`.
+    WindBefore {
+        before: Procedure,
+        thunk: Procedure,
+        after: Procedure,
+    },
+    /// The thunk of a `dynamic-wind`, in the given extent.
+    Wind(Rc<(Procedure, Procedure)>),
+    /// A call whose value is replaced by the given one, like the after thunk of a
+    /// `dynamic-wind`.
+    Value(LValue),
+    /// The producer of a `call-with-values`, whose values go to the consumer.
+    Consumer(Procedure),
+    /// The body of a promise being forced, and whether it came from a `delay-force`.
+    Force(Rc<Promise>, bool),
+}
+
+/// A run of the evaluator, started by a top-level form or by a builtin that calls back into
+/// Scheme code, along with the number of frames and restarts that were there before it.
+struct Run {
+    id: usize,
+    top_level: bool,
+    frames: usize,
+    restarts: usize,
+}
+
+/// A continuation captured by `call/cc`: the frames of the run of the evaluator that captured
+/// it, and the dynamic state they were evaluated in.
+pub struct Continuation {
+    run: usize,
+    top_level: bool,
+    frames: Vec<Pending>,
+    handlers: Vec<Procedure>,
+    /// The restarts, the first `base_restarts` of which were there before the run.
+    restarts: Vec<Restart>,
+    base_restarts: usize,
+    winds: Vec<Rc<(Procedure, Procedure)>>,
+    parameterizations: Vec<(Rc<Parameter>, LValue)>,
+}
+
+impl fmt::Debug for Continuation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // The frames hold environments, which would only show as frames of their own.
+        write!(f, "Continuation({} frames)", self.frames.len())
+    }
+}
+
+/// Takes the values of `u` if it's an escape to the exit point `id`.
+fn escape_values(u: &mut Unwind, id: usize) -> Option<Vec<LValue>> {
+    match *u {
+        Unwind::Escape(target, ref mut values) if target == id => Some(mem::take(values)),
+        _ => None,
+    }
+}
 
 pub struct Program {
-    stack: Vec<HashMap<String, LValue>>,
+    /// The global environment, where top-level forms are evaluated.
+    global: Environment,
+    /// The environment of the expression being evaluated, or of the call to a builtin.
+    environment: Environment,
+    /// The environment holding the builtins and the standard library, which the global
+    /// environment extends.
+    system: Environment,
+    handlers: Vec<Procedure>,
+    restarts: Vec<Restart>,
+    /// The `(before . after)` thunks of the `dynamic-wind` extents being evaluated. Each
+    /// extent is shared with its frame and the continuations captured in it.
+    winds: Vec<Rc<(Procedure, Procedure)>>,
+    /// The values given to parameter objects by the `parameterize` forms being evaluated,
+    /// innermost last.
+    parameterizations: Vec<(Rc<Parameter>, LValue)>,
+    /// The forms and calls waiting for a value, innermost last. They're kept off the Rust
+    /// stack, so that Scheme code can recurse as deeply as memory allows, and so that
+    /// continuations can copy them.
+    frames: Vec<Pending>,
+    /// The runs of the evaluator in progress, innermost last.
+    runs: Vec<Run>,
+    next_exit_id: usize,
+    interactive: bool,
+    /// The port the REPL echoes results to.
+    console: Rc<RefCell<Port>>,
+    current_input: Rc<Parameter>,
+    current_output: Rc<Parameter>,
+    current_error: Rc<Parameter>,
+    /// The libraries defined so far, by name.
+    libraries: HashMap<String, Rc<Library>>,
+    /// The names of the libraries whose files are being loaded, to detect cyclic imports.
+    loading_libraries: Vec<String>,
+    /// The directories searched for the files of libraries that aren't defined yet.
+    library_path: Vec<String>,
+    /// The files being loaded, innermost last.
+    sources: Vec<SourceFile>,
+}
+
+/// Reads every datum in `code`.
+fn parse_code(code: &str) -> Result<Vec<ListNode>, String> {
+    let mut port = Port::open_input_string(code);
+    let mut data = Vec::new();
+    while let Some(datum) = ListNode::read(&mut port)? {
+        data.push(datum);
+    }
+    Ok(data)
+}
+
+fn describe_raised(obj: &LValue) -> String {
+    match *obj {
+        LValue::Condition(ref c) => c.to_string(),
+        _ => format!("The object {} was raised and not handled.", obj),
+    }
+}
+
+/// Whether `value` satisfies the type predicate `p`.
+fn has_type(p: &Procedure, value: &LValue) -> bool {
+    match (p, value) {
+        (Procedure::IsPromise, LValue::Promise(_)) |
+        (Procedure::IsNull, LValue::Nil) |
+        (Procedure::IsPair, LValue::Pair(_)) |
+        (Procedure::IsNumber, LValue::NumericalValue(_)) |
+        (Procedure::IsComplex, LValue::NumericalValue(_)) |
+        (Procedure::IsReal, LValue::NumericalValue(_)) |
+        (Procedure::IsString, LValue::StringValue(_)) |
+        (Procedure::IsBoolean, LValue::BooleanValue(_)) |
+        (Procedure::IsChar, LValue::Character(_)) |
+        (Procedure::IsVector, LValue::Vector(_)) |
+        (Procedure::IsBytevector, LValue::Bytevector(_)) |
+        (Procedure::IsSymbol, LValue::Symbol(_)) |
+        (Procedure::IsApplicable, LValue::Procedure(_)) |
+        (Procedure::IsPort, LValue::Port(_)) |
+        (Procedure::IsEnvironment, LValue::Environment(_)) |
+        (Procedure::IsRestart, LValue::Restart(_)) |
+        (Procedure::IsHashTable, LValue::HashTable(_)) => true,
+        (Procedure::IsRational, LValue::NumericalValue(x)) => x.is_finite(),
+        (Procedure::IsInteger, LValue::NumericalValue(x)) => x.is_finite() && x.fract() == 0.0,
+        (Procedure::IsInputPort, LValue::Port(port)) => port.borrow().is_input(),
+        (Procedure::IsOutputPort, LValue::Port(port)) => port.borrow().is_output(),
+        (Procedure::IsTextualPort, LValue::Port(port)) => !port.borrow().binary,
+        (Procedure::IsBinaryPort, LValue::Port(port)) => port.borrow().binary,
+        (Procedure::IsList, _) => value.is_list(),
+        _ => false,
+    }
+}
+
+fn count_arguments(n: usize) -> String {
+    format!("{} argument{}", n, if n == 1 { "" } else { "s" })
+}
+
+/// The error for a call to the builtin `p` with the wrong number of arguments.
+fn arity_error(p: &Procedure, given: usize) -> Unwind {
+    let expected = match p.arity() {
+        (0, Some(0)) => "no arguments".to_string(),
+        (min, Some(max)) if min == max => format!("exactly {}", count_arguments(min)),
+        (min, Some(max)) => format!("{} to {}", min, count_arguments(max)),
+        (min, None) => format!("at least {}", count_arguments(min)),
+    };
+    Unwind::Error(format!("'{}' requires {}, but got {}.", p.name(), expected, given))
+}
+
+/// The error for an argument of the builtin `p` that isn't of the `expected` type. The
+/// index counts from 0, but the message counts from 1.
+fn wrong_type(p: &Procedure, index: usize, expected: &str, value: &LValue) -> Unwind {
+    Unwind::Error(format!("'{}' expects {} as argument {}, but got {}.",
+                          p.name(),
+                          expected,
+                          index + 1,
+                          value))
+}
+
+fn number_argument(p: &Procedure, args: &[LValue], index: usize) -> Result<f64, Unwind> {
+    match args[index] {
+        LValue::NumericalValue(x) => Ok(x),
+        ref other => Err(wrong_type(p, index, "a number", other)),
+    }
+}
+
+/// Returns the argument at `index` as a count or an index, which must be an exact
+/// non-negative integer. Larger integers than any sequence could hold are refused, so that
+/// they don't saturate to `usize::MAX`.
+fn index_argument(p: &Procedure, args: &[LValue], index: usize) -> Result<usize, Unwind> {
+    match args[index] {
+        LValue::NumericalValue(x) if x >= 0.0 && x.fract() == 0.0 && x.is_finite() => {
+            if x >= isize::MAX as f64 {
+                let expected = format!("an integer from 0 to {}", isize::MAX);
+                return Err(wrong_type(p, index, &expected, &args[index]));
+            }
+            Ok(x as usize)
+        }
+        ref other => Err(wrong_type(p, index, "a non-negative integer", other)),
+    }
+}
+
+/// Returns the argument at `index` as an index into a sequence of the given length.
+fn element_index(p: &Procedure,
+                 args: &[LValue],
+                 index: usize,
+                 length: usize)
+                 -> Result<usize, Unwind> {
+    let k = index_argument(p, args, index)?;
+    if k >= length {
+        let expected = format!("an index less than {}", length);
+        return Err(wrong_type(p, index, &expected, &args[index]));
+    }
+    Ok(k)
+}
+
+/// Reads the optional start and end arguments at `index` and `index + 1`, which select a
+/// range of a sequence of the given length. They default to the whole sequence.
+fn range_arguments(p: &Procedure,
+                   args: &[LValue],
+                   index: usize,
+                   length: usize)
+                   -> Result<(usize, usize), Unwind> {
+    let end = if args.len() > index + 1 {
+        let end = index_argument(p, args, index + 1)?;
+        if end > length {
+            let expected = format!("an index up to {}", length);
+            return Err(wrong_type(p, index + 1, &expected, &args[index + 1]));
+        }
+        end
+    } else {
+        length
+    };
+    let start = if args.len() > index {
+        let start = index_argument(p, args, index)?;
+        if start > end {
+            let expected = format!("an index up to {}", end);
+            return Err(wrong_type(p, index, &expected, &args[index]));
+        }
+        start
+    } else {
+        0
+    };
+    Ok((start, end))
+}
+
+/// Copies the range of `from` selected by the arguments at 3 and 4 into `to`, starting at
+/// the index given by the argument at 1, as `vector-copy!` and `bytevector-copy!` do.
+fn copy_range<T: Clone>(p: &Procedure,
+                        args: &[LValue],
+                        to: &Boxed<Vec<T>>,
+                        from: &RefCell<Vec<T>>)
+                        -> Result<LValue, Unwind> {
+    check_mutable(p, args, 0, to)?;
+    // Copying the source first allows the two ranges to overlap.
+    let items = {
+        let from = from.borrow();
+        let (start, end) = range_arguments(p, args, 3, from.len())?;
+        from[start..end].to_vec()
+    };
+    let at = index_argument(p, args, 1)?;
+    let mut to = to.borrow_mut();
+    match at.checked_add(items.len()) {
+        Some(end) if end <= to.len() => {
+            to[at..end].clone_from_slice(&items);
+            Ok(LValue::Undefined)
+        }
+        _ => {
+            let last = to.len().saturating_sub(items.len());
+            let expected = format!("an index up to {}", last);
+            Err(wrong_type(p, 1, &expected, &args[1]))
+        }
+    }
+}
+
+/// Refuses to let `p` modify its argument at `index`, whose contents are `contents`, if it
+/// is a literal.
+fn check_mutable<T>(p: &Procedure,
+                    args: &[LValue],
+                    index: usize,
+                    contents: &Boxed<T>)
+                    -> Result<(), Unwind> {
+    if contents.immutable {
+        let expected = format!("a mutable {}", args[index].type_name());
+        return Err(wrong_type(p, index, &expected, &args[index]));
+    }
+    Ok(())
+}
+
+fn string_argument(p: &Procedure,
+                   args: &[LValue],
+                   index: usize)
+                   -> Result<Rc<Boxed<String>>, Unwind> {
+    match args[index] {
+        LValue::StringValue(ref s) => Ok(s.clone()),
+        ref other => Err(wrong_type(p, index, "a string", other)),
+    }
+}
+
+/// Returns the characters of the string argument at `index`, for indexing into them.
+fn string_chars(p: &Procedure, args: &[LValue], index: usize) -> Result<Vec<char>, Unwind> {
+    Ok(string_argument(p, args, index)?.borrow().chars().collect())
+}
+
+fn char_argument(p: &Procedure, args: &[LValue], index: usize) -> Result<char, Unwind> {
+    match args[index] {
+        LValue::Character(c) => Ok(c),
+        ref other => Err(wrong_type(p, index, "a character", other)),
+    }
+}
+
+fn vector_argument(p: &Procedure,
+                   args: &[LValue],
+                   index: usize)
+                   -> Result<Rc<Boxed<Vec<LValue>>>, Unwind> {
+    match args[index] {
+        LValue::Vector(ref v) => Ok(v.clone()),
+        ref other => Err(wrong_type(p, index, "a vector", other)),
+    }
+}
+
+fn bytevector_argument(p: &Procedure,
+                       args: &[LValue],
+                       index: usize)
+                       -> Result<Rc<Boxed<Vec<u8>>>, Unwind> {
+    match args[index] {
+        LValue::Bytevector(ref v) => Ok(v.clone()),
+        ref other => Err(wrong_type(p, index, "a bytevector", other)),
+    }
+}
+
+fn byte_argument(p: &Procedure, args: &[LValue], index: usize) -> Result<u8, Unwind> {
+    match args[index] {
+        LValue::NumericalValue(x) if (0.0..=255.0).contains(&x) && x.fract() == 0.0 => {
+            Ok(x as u8)
+        }
+        ref other => Err(wrong_type(p, index, "a byte", other)),
+    }
+}
+
+/// Reads a homogeneous vector with elements of type `t`. The u8 vectors are bytevectors.
+fn numeric_vector_argument(p: &Procedure,
+                           args: &[LValue],
+                           index: usize,
+                           t: ElementType)
+                           -> Result<TypedVector, Unwind> {
+    match (t, &args[index]) {
+        (ElementType::U8, LValue::Bytevector(v)) => Ok(TypedVector::Bytes(v.clone())),
+        (_, LValue::NumericVector(v)) if v.borrow().element_type() == t => {
+            Ok(TypedVector::Numbers(v.clone()))
+        }
+        (_, other) => {
+            // The letters f and s are read with a vowel first, unlike u.
+            let article = if t.tag().starts_with('u') { "a" } else { "an" };
+            let expected = format!("{} {}", article, procedure_name(t, NumericVectorOp::Construct));
+            Err(wrong_type(p, index, &expected, other))
+        }
+    }
+}
+
+/// Reads a number that a homogeneous vector with elements of type `t` can hold.
+fn element_argument(p: &Procedure,
+                    args: &[LValue],
+                    index: usize,
+                    t: ElementType)
+                    -> Result<f64, Unwind> {
+    match args[index] {
+        LValue::NumericalValue(x) if t.accepts(x) => Ok(x),
+        ref other => Err(wrong_type(p, index, t.description(), other)),
+    }
+}
+
+fn hash_table_argument(p: &Procedure,
+                       args: &[LValue],
+                       index: usize)
+                       -> Result<Rc<RefCell<HashTable>>, Unwind> {
+    match args[index] {
+        LValue::HashTable(ref t) => Ok(t.clone()),
+        ref other => Err(wrong_type(p, index, "a hash table", other)),
+    }
+}
+
+/// Reads a key for `table`. Tables compared with `string=?` only take strings.
+fn key_argument(p: &Procedure,
+                table: &Rc<RefCell<HashTable>>,
+                args: &[LValue],
+                index: usize)
+                -> Result<LValue, Unwind> {
+    match args[index] {
+        LValue::StringValue(_) => Ok(args[index].clone()),
+        ref other if table.borrow().equivalence == Equivalence::StringEqual => {
+            Err(wrong_type(p, index, "a string key", other))
+        }
+        ref other => Ok(other.clone()),
+    }
+}
+
+fn symbol_argument(p: &Procedure, args: &[LValue], index: usize) -> Result<String, Unwind> {
+    match args[index] {
+        LValue::Symbol(ref s) => Ok(s.clone()),
+        ref other => Err(wrong_type(p, index, "a symbol", other)),
+    }
+}
+
+/// Whether `ord`, the ordering of two adjacent arguments, satisfies the comparison `p`.
+fn ordering_holds(p: &Procedure, ord: Ordering) -> bool {
+    match *p {
+        Procedure::Equal | Procedure::StringEqual | Procedure::CharEqual |
+        Procedure::CharEqualCi => ord == Ordering::Equal,
+        Procedure::Less | Procedure::StringLess | Procedure::CharLess | Procedure::CharLessCi => {
+            ord == Ordering::Less
+        }
+        Procedure::Greater | Procedure::StringGreater | Procedure::CharGreater |
+        Procedure::CharGreaterCi => ord == Ordering::Greater,
+        Procedure::LessEqual | Procedure::StringLessEqual | Procedure::CharLessEqual |
+        Procedure::CharLessEqualCi => ord != Ordering::Greater,
+        _ => ord != Ordering::Less,
+    }
+}
+
+/// Returns every argument, all of which must be numbers.
+fn number_arguments(p: &Procedure, args: &[LValue]) -> Result<Vec<f64>, Unwind> {
+    (0..args.len()).map(|i| number_argument(p, args, i)).collect()
+}
+
+/// Checks that the first argument designates a restart, by name or as a restart object.
+fn restart_designator(p: &Procedure, args: &[LValue]) -> Result<(), Unwind> {
+    match args[0] {
+        LValue::Symbol(_) | LValue::Restart(_) => Ok(()),
+        ref other => Err(wrong_type(p, 0, "a restart or a restart name", other)),
+    }
+}
+
+fn procedure_argument(p: &Procedure, args: &[LValue], index: usize) -> Result<Procedure, Unwind> {
+    match args[index] {
+        LValue::Procedure(ref procedure) => Ok(procedure.clone()),
+        ref other => Err(wrong_type(p, index, "a procedure", other)),
+    }
+}
+
+fn port_argument(p: &Procedure,
+                 args: &[LValue],
+                 index: usize)
+                 -> Result<Rc<RefCell<Port>>, Unwind> {
+    match args[index] {
+        LValue::Port(ref port) => Ok(port.clone()),
+        ref other => Err(wrong_type(p, index, "a port", other)),
+    }
+}
+
+fn environment_argument(p: &Procedure,
+                        args: &[LValue],
+                        index: usize)
+                        -> Result<Environment, Unwind> {
+    match args[index] {
+        LValue::Environment(ref env) => Ok(env.clone()),
+        ref other => Err(wrong_type(p, index, "an environment", other)),
+    }
 }
 
 impl Program {
     pub fn new() -> Program {
-        let mut p = Program { stack: Vec::new() };
+        let console = Rc::new(RefCell::new(Port::console()));
+        let current_input = Rc::new(Parameter {
+            value: LValue::Port(Rc::new(RefCell::new(Port::console_input()))),
+            converter: None,
+        });
+        let current_output = Rc::new(Parameter {
+            value: LValue::Port(console.clone()),
+            converter: None,
+        });
+        let current_error = Rc::new(Parameter {
+            value: LValue::Port(Rc::new(RefCell::new(Port::console_error()))),
+            converter: None,
+        });
+        // Libraries are looked up in the current directory, then in RSCHEME_LIBRARY_PATH.
+        let mut library_path = vec![".".to_string()];
+        if let Ok(path) = env::var("RSCHEME_LIBRARY_PATH") {
+            library_path.extend(path.split(':').filter(|d| !d.is_empty()).map(String::from));
+        }
+        let system = Frame::new(HashMap::new(), None);
+        let global = Frame::new(HashMap::new(), Some(system.clone()));
+        let mut p = Program {
+            global: global.clone(),
+            environment: global,
+            system,
+            handlers: Vec::new(),
+            restarts: Vec::new(),
+            winds: Vec::new(),
+            parameterizations: Vec::new(),
+            frames: Vec::new(),
+            runs: Vec::new(),
+            next_exit_id: 0,
+            interactive: false,
+            console,
+            current_input,
+            current_output,
+            current_error,
+            libraries: HashMap::new(),
+            loading_libraries: Vec::new(),
+            library_path,
+            sources: Vec::new(),
+        };
         p.initialize();
         p
     }
 
+    /// Runs every top-level form of `code`. Unless `silent` is set, results are echoed and
+    /// unhandled conditions enter the debugger instead of aborting the form.
     pub fn run_code(&mut self, code: String, silent: bool) {
-        let tokens = parser::parse_primitives(&code);
-        let mut token_iter = tokens.into_iter();
-        let list_tree = ListNode::from_primitive_tokens(&mut token_iter, false);
-        match list_tree {
-            ListNode::Node(_, ref v) => {
-                for e in v {
-                    match Expression::from_list(e) {
-                        Ok(res) => {
-                            match self.evaluate_expression(&res) {
-                                Ok(result) => {
-                                    if !silent {
-                                        println!("{}", result)
+        let mut port = Port::open_input_string(&code);
+        self.run_port(&mut port, silent);
+    }
+
+    /// Runs a file given on the command line. Errors are reported along with their location,
+    /// and don't stop the rest of the file from running.
+    pub fn run_file(&mut self, path: &str) {
+        let mut port = match Port::open_input_file(path) {
+            Ok(port) => port,
+            Err(_) => {
+                println!("Failed to open file {}.", path);
+                return;
+            }
+        };
+        self.sources.push(SourceFile {
+            path: PathBuf::from(path),
+            position: port.position(),
+        });
+        self.run_port(&mut port, true);
+        self.sources.pop();
+    }
+
+    fn run_port(&mut self, port: &mut Port, silent: bool) {
+        self.interactive = !silent;
+        loop {
+            let e = match ListNode::read(port) {
+                Ok(Some(e)) => e,
+                Ok(None) => break,
+                Err(s) => {
+                    match port.location() {
+                        Some(location) => println!("Syntax error: {}: {}", location, s),
+                        None => println!("Syntax error: {}", s),
+                    }
+                    break;
+                }
+            };
+            if port.name.is_some() {
+                self.sources.last_mut().unwrap().position = port.token_start;
+            }
+            match Expression::from_top_level(&e, &self.source_directory()) {
+                Ok(res) => {
+                    match self.evaluate_top_level(&Rc::new(res)) {
+                        Ok(Some(result)) => {
+                            if !silent {
+                                self.echo(result)
+                            }
+                        }
+                        Ok(None) => break,
+                        Err(Unwind::Error(s)) => println!("Runtime error: {}", s),
+                        Err(Unwind::Signal(c)) => println!("Runtime error: {}", c),
+                        Err(Unwind::Raise(obj)) => {
+                            println!("Runtime error: {}", describe_raised(&obj))
+                        }
+                        Err(Unwind::Escape(..)) | Err(Unwind::Reenter(..)) => {
+                            println!("Runtime error: Control escaped to an exit point that is \
+                                      no longer active.")
+                        }
+                    }
+                }
+                Err(s) => {
+                    match self.location() {
+                        Some(location) => println!("Syntax error: {}: {}", location, s),
+                        None => println!("Syntax error: {}", s),
+                    }
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Describes where the form being evaluated came from, if it was loaded from a file.
+    fn location(&self) -> Option<String> {
+        self.sources.last().map(|source| format!("{}:{}", source.path.display(), source.position))
+    }
+
+    /// The directory of the file being loaded, which file names are relative to.
+    fn source_directory(&self) -> PathBuf {
+        match self.sources.last().and_then(|source| source.path.parent()) {
+            Some(dir) => dir.to_path_buf(),
+            None => PathBuf::new(),
+        }
+    }
+
+    /// Resolves a file name relative to the directory of the file being loaded.
+    fn resolve_path(&self, name: &str) -> PathBuf {
+        self.source_directory().join(name)
+    }
+
+    /// Prints the result of a top-level form, one line per value.
+    fn echo(&mut self, result: LValue) {
+        self.fresh_line();
+        let mut console = self.console.borrow_mut();
+        for value in result.into_values() {
+            let _ = console.write_str(&format!("{}\n", value));
+        }
+    }
+
+    /// Ends the line that output left unfinished on the console, if any.
+    fn fresh_line(&mut self) {
+        let mut console = self.console.borrow_mut();
+        if !console.line_start {
+            let _ = console.write_str("\n");
+        }
+    }
+
+    /// Evaluates a top-level form with the `abort` and `retry` restarts in effect. Returns
+    /// `None` if the form was aborted.
+    fn evaluate_top_level(&mut self, e: &Rc<Expression>) -> Result<Option<LValue>, Unwind> {
+        let depth = self.restarts.len();
+        loop {
+            let abort = self.push_restart("abort", "Return to the REPL.", 0);
+            let retry = self.push_restart("retry", "Re-evaluate the top-level form.", 0);
+            let environment = self.environment.clone();
+            let result = self.run(Step::Eval(e.clone(), environment), true);
+            self.restarts.truncate(depth);
+            match result {
+                Ok(v) => return Ok(Some(v)),
+                Err(Unwind::Escape(id, _)) if id == retry => continue,
+                Err(Unwind::Escape(id, _)) if id == abort => return Ok(None),
+                Err(u) => return Err(u),
+            }
+        }
+    }
+
+    pub fn initialize(&mut self) {
+        // Add the basic functions.
+        let mut basic_map: HashMap<String, LValue> = HashMap::new();
+        for p in Procedure::builtins() {
+            basic_map.insert(p.name().to_string(), LValue::Procedure(p));
+        }
+        basic_map.insert("call/cc".to_string(),
+                         LValue::Procedure(Procedure::CallWithCurrentContinuation));
+        basic_map.insert("current-input-port".to_string(),
+                         LValue::Procedure(Procedure::Parameter(self.current_input.clone())));
+        basic_map.insert("current-output-port".to_string(),
+                         LValue::Procedure(Procedure::Parameter(self.current_output.clone())));
+        basic_map.insert("current-error-port".to_string(),
+                         LValue::Procedure(Procedure::Parameter(self.current_error.clone())));
+        self.system.borrow_mut().bindings = basic_map;
+    }
+
+    /// Runs the standard library. Its definitions go to the system environment, so that
+    /// they're also part of the environments made by `scheme-report-environment`.
+    pub fn run_prelude(&mut self, code: String) {
+        let global = mem::replace(&mut self.environment, self.system.clone());
+        self.run_code(code, true);
+        self.environment = global;
+    }
+
+    /// Returns a fresh id for a restart to be the target of an escape, or for a run of the
+    /// evaluator.
+    fn new_exit_id(&mut self) -> usize {
+        self.next_exit_id += 1;
+        self.next_exit_id
+    }
+
+    fn push_restart(&mut self, name: &str, description: &str, arity: usize) -> usize {
+        let id = self.new_exit_id();
+        self.restarts.push(Restart {
+            id,
+            name: name.to_string(),
+            description: description.to_string(),
+            arity,
+        });
+        id
+    }
+
+    /// Hands a raised object to the innermost exception handler, or to the debugger when no
+    /// handlers are left. The handler runs with the outer handlers in effect.
+    fn signal(&mut self, obj: LValue, continuable: bool) -> Result<Step, Unwind> {
+        let handler = match self.handlers.pop() {
+            Some(h) => h,
+            None => {
+                return if self.interactive {
+                    self.debug(obj).map(Step::Return)
+                } else {
+                    Err(Unwind::Raise(obj))
+                };
+            }
+        };
+        self.frames.push(Pending::Handler(handler.clone()));
+        if !continuable {
+            self.frames.push(Pending::NonContinuable);
+        }
+        Ok(Step::Call(handler, vec![obj]))
+    }
+
+    /// A nested REPL for unhandled conditions: lists the active restarts and lets the user
+    /// either pick one by number or evaluate expressions where the error occurred.
+    fn debug(&mut self, obj: LValue) -> Result<LValue, Unwind> {
+        let restarts: Vec<Restart> = self.restarts.iter().rev().cloned().collect();
+        self.fresh_line();
+        println!(";Unhandled condition: {}", describe_raised(&obj));
+        println!(";To continue, choose one of the following restarts:");
+        for (i, r) in restarts.iter().enumerate() {
+            println!("; ({}) {} - {}", i, r.name, r.description);
+        }
+        loop {
+            let line = match Program::prompt("debug> ") {
+                Some(line) => line,
+                None => return Err(Unwind::Raise(obj)),
+            };
+            if let Ok(index) = line.trim().parse::<usize>() {
+                if index >= restarts.len() {
+                    println!(";There is no restart numbered {}.", index);
+                    continue;
+                }
+                let mut args = Vec::new();
+                while args.len() < restarts[index].arity {
+                    match Program::prompt("value> ") {
+                        Some(line) => {
+                            match parse_code(&line) {
+                                Ok(data) => {
+                                    for e in &data {
+                                        args.push(self.debug_evaluate(e)?);
                                     }
                                 }
-                                Err(s) => println!("Runtime error: {}", s),
+                                Err(s) => println!(";Syntax error: {}", s),
+                            }
+                        }
+                        None => return Err(Unwind::Raise(obj)),
+                    }
+                }
+                args.truncate(restarts[index].arity);
+                return Err(Unwind::Escape(restarts[index].id, args));
+            }
+            match parse_code(&line) {
+                Ok(data) => {
+                    for e in &data {
+                        let result = self.debug_evaluate(e)?;
+                        self.echo(result);
+                    }
+                }
+                Err(s) => println!(";Syntax error: {}", s),
+            }
+        }
+    }
+
+    /// Evaluates a form typed into the debugger, under an `abort` restart that returns to it.
+    fn debug_evaluate(&mut self, e: &ListNode) -> Result<LValue, Unwind> {
+        let expression = Expression::from_top_level(e, &self.source_directory());
+        let expression = Rc::new(expression.map_err(Unwind::Error)?);
+        let abort = self.push_restart("abort", "Return to the debugger.", 0);
+        let environment = self.environment.clone();
+        let result = self.evaluate_in(&expression, environment);
+        self.restarts.pop();
+        match result {
+            Err(Unwind::Escape(id, _)) if id == abort => Ok(LValue::Undefined),
+            result => result,
+        }
+    }
+
+    fn prompt(text: &str) -> Option<String> {
+        print!("{}", text);
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        match io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line),
+        }
+    }
+
+    /// Returns the textual output port given as the argument at `index`, or the current
+    /// output port if there's no such argument.
+    fn output_port(&self,
+                   p: &Procedure,
+                   args: &[LValue],
+                   index: usize)
+                   -> Result<Rc<RefCell<Port>>, Unwind> {
+        self.port_of_kind(p, args, index, false, false)
+    }
+
+    /// Returns the textual input port given as the argument at `index`, or the current input
+    /// port if there's no such argument.
+    fn input_port(&self,
+                  p: &Procedure,
+                  args: &[LValue],
+                  index: usize)
+                  -> Result<Rc<RefCell<Port>>, Unwind> {
+        self.port_of_kind(p, args, index, true, false)
+    }
+
+    fn binary_output_port(&self,
+                          p: &Procedure,
+                          args: &[LValue],
+                          index: usize)
+                          -> Result<Rc<RefCell<Port>>, Unwind> {
+        self.port_of_kind(p, args, index, false, true)
+    }
+
+    fn binary_input_port(&self,
+                         p: &Procedure,
+                         args: &[LValue],
+                         index: usize)
+                         -> Result<Rc<RefCell<Port>>, Unwind> {
+        self.port_of_kind(p, args, index, true, true)
+    }
+
+    /// Returns the port given as the argument at `index`, checking that it's an input or an
+    /// output port, and a binary or a textual one. Without such an argument, it's the current
+    /// input or output port.
+    fn port_of_kind(&self,
+                    p: &Procedure,
+                    args: &[LValue],
+                    index: usize,
+                    input: bool,
+                    binary: bool)
+                    -> Result<Rc<RefCell<Port>>, Unwind> {
+        let port = match args.get(index) {
+            Some(port) => port.clone(),
+            None if input => self.parameter_value(&self.current_input),
+            None => self.parameter_value(&self.current_output),
+        };
+        if let LValue::Port(ref port) = port {
+            let kind = {
+                let port = port.borrow();
+                (if input { port.is_input() } else { port.is_output() }) && port.binary == binary
+            };
+            if kind {
+                return Ok(port.clone());
+            }
+        }
+        let expected = format!("a {} {} port",
+                               if binary { "binary" } else { "textual" },
+                               if input { "input" } else { "output" });
+        Err(wrong_type(p, index, &expected, &port))
+    }
+
+    /// Looks up the key given as argument 1 in the hash table given as argument 0, like
+    /// `hash-table-ref`. The optional failure thunk and success procedure are the arguments
+    /// at `index` and `index + 1`.
+    fn hash_table_ref(&mut self,
+                      p: &Procedure,
+                      args: &[LValue],
+                      index: usize)
+                      -> Result<LValue, Unwind> {
+        let table = hash_table_argument(p, args, 0)?;
+        let key = key_argument(p, &table, args, 1)?;
+        let failure = match args.get(index) {
+            Some(_) => Some(procedure_argument(p, args, index)?),
+            None => None,
+        };
+        let success = match args.get(index + 1) {
+            Some(_) => Some(procedure_argument(p, args, index + 1)?),
+            None => None,
+        };
+        let value = table.borrow().get(&key);
+        match (value, failure, success) {
+            (Some(value), _, Some(success)) => self.evaluate_call(&success, &[value]),
+            (Some(value), _, None) => Ok(value),
+            (None, Some(failure), _) => self.evaluate_call(&failure, &[]),
+            (None, None, _) => {
+                Err(Unwind::Error(format!("'{}' found no value for the key {}.", p.name(), key)))
+            }
+        }
+    }
+
+    /// Calls a thunk with the current output port bound to `port`.
+    fn with_output_to(&mut self,
+                      port: &Rc<RefCell<Port>>,
+                      thunk: &Procedure)
+                      -> Result<LValue, Unwind> {
+        let depth = self.parameterizations.len();
+        self.parameterizations.push((self.current_output.clone(), LValue::Port(port.clone())));
+        let result = self.evaluate_call(thunk, &Vec::new());
+        self.parameterizations.truncate(depth);
+        result
+    }
+
+    /// Returns the bindings made available by an import set.
+    fn import(&mut self, set: &ImportSet) -> Result<HashMap<String, LValue>, Unwind> {
+        let check = |bindings: &HashMap<String, LValue>, name: &str| {
+            if bindings.contains_key(name) {
+                Ok(())
+            } else {
+                Err(Unwind::Error(format!("'{}' isn't part of the import set.", name)))
+            }
+        };
+        match *set {
+            // The builtins and the standard library make up every standard library.
+            ImportSet::Library(ref name) if is_standard_library(name) => {
+                Ok(self.system.borrow().bindings.clone())
+            }
+            ImportSet::Library(ref name) => Ok(self.find_library(name)?.exports.clone()),
+            ImportSet::Only(ref inner, ref names) => {
+                let mut bindings = self.import(inner)?;
+                for name in names {
+                    check(&bindings, name)?;
+                }
+                bindings.retain(|k, _| names.contains(k));
+                Ok(bindings)
+            }
+            ImportSet::Except(ref inner, ref names) => {
+                let mut bindings = self.import(inner)?;
+                for name in names {
+                    check(&bindings, name)?;
+                    bindings.remove(name);
+                }
+                Ok(bindings)
+            }
+            ImportSet::Prefix(ref inner, ref prefix) => {
+                let bindings = self.import(inner)?;
+                Ok(bindings.into_iter().map(|(k, v)| (format!("{}{}", prefix, k), v)).collect())
+            }
+            ImportSet::Rename(ref inner, ref renames) => {
+                let mut bindings = self.import(inner)?;
+                for (from, to) in renames {
+                    check(&bindings, from)?;
+                    let value = bindings.remove(from).unwrap();
+                    bindings.insert(to.clone(), value);
+                }
+                Ok(bindings)
+            }
+        }
+    }
+
+    /// Returns the library with the given name, loading it from the library path if it
+    /// hasn't been defined yet. A library named `(foo bar)` is looked for in `foo/bar.sld`.
+    fn find_library(&mut self, name: &[String]) -> Result<Rc<Library>, Unwind> {
+        let key = library_key(name);
+        if let Some(library) = self.libraries.get(&key) {
+            return Ok(library.clone());
+        }
+        if self.loading_libraries.contains(&key) {
+            return Err(Unwind::Error(format!("The library {} imports itself.", key)));
+        }
+        let path = match self.library_file(name) {
+            Some(path) => path,
+            None => {
+                return Err(Unwind::Error(format!("Can't find the library {} in the library \
+                                                  path.",
+                                                 key)))
+            }
+        };
+        self.loading_libraries.push(key.clone());
+        let global = self.global.clone();
+        let result = self.load_file(&path, global, false);
+        self.loading_libraries.pop();
+        result?;
+        match self.libraries.get(&key) {
+            Some(library) => Ok(library.clone()),
+            None => {
+                Err(Unwind::Error(format!("The file {} doesn't define the library {}.",
+                                          path.display(),
+                                          key)))
+            }
+        }
+    }
+
+    fn library_file(&self, name: &[String]) -> Option<PathBuf> {
+        let file_name = format!("{}.sld", name.join("/"));
+        self.library_path
+            .iter()
+            .map(|dir| Path::new(dir).join(&file_name))
+            .find(|path| path.is_file())
+    }
+
+    /// Evaluates a `define-library` form, adding the library it defines to the known ones.
+    fn define_library(&mut self, definition: &LibraryDefinition) -> Result<(), Unwind> {
+        let environment = Frame::new(HashMap::new(), None);
+        let mut names = Vec::new();
+        self.evaluate_declarations(&definition.declarations, &environment, &mut names)?;
+        let mut exports = HashMap::new();
+        for (internal, external) in names {
+            match Frame::lookup(&environment, &internal) {
+                Some(value) => {
+                    exports.insert(external, value);
+                }
+                None => {
+                    return Err(Unwind::Error(format!("The library {} exports the undefined \
+                                                      identifier '{}'.",
+                                                     library_key(&definition.name),
+                                                     internal)))
+                }
+            }
+        }
+        self.libraries.insert(library_key(&definition.name), Rc::new(Library { exports }));
+        Ok(())
+    }
+
+    /// Evaluates the declarations of a library in its environment, collecting the names it
+    /// exports.
+    fn evaluate_declarations(&mut self,
+                             declarations: &[LibraryDeclaration],
+                             environment: &Environment,
+                             exports: &mut Vec<(String, String)>)
+                             -> Result<(), Unwind> {
+        for declaration in declarations {
+            match *declaration {
+                LibraryDeclaration::Export(ref names) => exports.extend(names.iter().cloned()),
+                LibraryDeclaration::Form(ref e) => {
+                    self.evaluate_in(e, environment.clone())?;
+                }
+                LibraryDeclaration::CondExpand(ref clauses) => {
+                    let clause = clauses.iter().find(|c| self.requirement_holds(&c.0));
+                    if let Some((_, body)) = clause {
+                        self.evaluate_declarations(body, environment, exports)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn requirement_holds(&self, requirement: &Requirement) -> bool {
+        match *requirement {
+            Requirement::Feature(ref feature) => features().contains(feature),
+            Requirement::Library(ref name) => {
+                is_standard_library(name) || self.libraries.contains_key(&library_key(name)) ||
+                self.library_file(name).is_some()
+            }
+            Requirement::And(ref requirements) => {
+                requirements.iter().all(|r| self.requirement_holds(r))
+            }
+            Requirement::Or(ref requirements) => {
+                requirements.iter().any(|r| self.requirement_holds(r))
+            }
+            Requirement::Not(ref r) => !self.requirement_holds(r),
+            Requirement::Else => true,
+        }
+    }
+
+    /// Evaluates every form in a file, in the given environment, returning the value of the
+    /// last one. With `fold_case`, identifiers are read as if in lower case.
+    fn load_file(&mut self,
+                 path: &Path,
+                 environment: Environment,
+                 fold_case: bool)
+                 -> Result<LValue, Unwind> {
+        let mut port = match Port::open_input_file(&path.to_string_lossy()) {
+            Ok(port) => port,
+            Err(e) => {
+                let message = format!("Can't open file '{}': {}.", path.display(), e);
+                return Err(Unwind::Signal(Condition::file_error(message, Vec::new())));
+            }
+        };
+        port.fold_case = fold_case;
+        self.sources.push(SourceFile {
+            path: path.to_path_buf(),
+            position: port.position(),
+        });
+        let result = self.evaluate_port(&mut port, environment);
+        self.sources.pop();
+        result
+    }
+
+    fn evaluate_port(&mut self,
+                     port: &mut Port,
+                     environment: Environment)
+                     -> Result<LValue, Unwind> {
+        let mut lres = LValue::Undefined;
+        loop {
+            let datum = match ListNode::read(port) {
+                Ok(Some(datum)) => datum,
+                Ok(None) => return Ok(lres),
+                Err(s) => {
+                    let mut condition = Condition::read_error(s, Vec::new());
+                    condition.location = port.location();
+                    return Err(Unwind::Signal(condition));
+                }
+            };
+            self.sources.last_mut().unwrap().position = port.token_start;
+            let e = match Expression::from_top_level(&datum, &self.source_directory()) {
+                Ok(e) => e,
+                Err(s) => {
+                    let mut condition = Condition::new(s, Vec::new());
+                    condition.location = self.location();
+                    return Err(Unwind::Signal(condition));
+                }
+            };
+            lres = self.evaluate_in(&Rc::new(e), environment.clone())?;
+        }
+    }
+
+    /// Opens the file named by the first argument of `p`, for reading or for writing
+    /// depending on the procedure.
+    fn open_file(p: &Procedure, args: &[LValue]) -> Result<Rc<RefCell<Port>>, Unwind> {
+        let path = string_argument(p, args, 0)?.borrow().clone();
+        let port = match *p {
+            Procedure::OpenInputFile | Procedure::CallWithInputFile => {
+                Port::open_input_file(&path)
+            }
+            Procedure::OpenBinaryInputFile => Port::open_binary_input_file(&path),
+            Procedure::OpenBinaryOutputFile => Port::open_binary_output_file(&path),
+            _ => Port::open_output_file(&path),
+        };
+        match port {
+            Ok(port) => Ok(Rc::new(RefCell::new(port))),
+            Err(e) => {
+                let message = format!("Can't open file '{}': {}.", path, e);
+                Err(Unwind::Signal(Condition::file_error(message, Vec::new())))
+            }
+        }
+    }
+
+    fn find_restart(&self, designator: &LValue) -> Option<Restart> {
+        match *designator {
+            LValue::Symbol(ref name) => self.restarts.iter().rev().find(|r| r.name == *name),
+            LValue::Restart(ref restart) => self.restarts.iter().find(|r| r.id == restart.id),
+            _ => None,
+        }
+        .cloned()
+    }
+
+    /// Evaluates an expression in the given environment, in a run of the evaluator of its own.
+    fn evaluate_in(&mut self, e: &Rc<Expression>, env: Environment) -> Result<LValue, Unwind> {
+        self.run(Step::Eval(e.clone(), env), false)
+    }
+
+    /// Calls a procedure from a builtin, in a run of the evaluator of its own.
+    fn evaluate_call(&mut self, p: &Procedure, args: &[LValue]) -> Result<LValue, Unwind> {
+        self.run(Step::Call(p.clone(), args.to_vec()), false)
+    }
+
+    /// Starts forcing a promise. When the body of a `delay-force` is done, the promise takes
+    /// over the state of the one it evaluated to and is forced anew, so that long chains of
+    /// them don't pile up frames.
+    fn force(&mut self, promise: &Rc<Promise>) -> Result<Step, Unwind> {
+        match promise.state() {
+            PromiseState::Forced(value) => Ok(Step::Return(value)),
+            PromiseState::Delayed { body, environment, delay_force } => {
+                self.frames.push(Pending::Force(promise.clone(), delay_force));
+                Ok(Step::Eval(body, environment))
+            }
+        }
+    }
+
+    /// Returns the value of a parameter object in the current dynamic environment.
+    fn parameter_value(&self, parameter: &Rc<Parameter>) -> LValue {
+        for (p, value) in self.parameterizations.iter().rev() {
+            if Rc::ptr_eq(p, parameter) {
+                return value.clone();
+            }
+        }
+        parameter.value.clone()
+    }
+
+    /// Passes a value given to a parameter object through its converter, if it has one.
+    fn convert_parameter(&mut self,
+                         parameter: &Parameter,
+                         value: LValue)
+                         -> Result<LValue, Unwind> {
+        match parameter.converter {
+            Some(ref converter) => self.evaluate_call(converter, &[value]),
+            None => Ok(value),
+        }
+    }
+
+    /// Calls a builtin procedure, other than the ones `apply` handles, with the right number
+    /// of arguments.
+    fn evaluate_builtin(&mut self, p: &Procedure, args: &[LValue]) -> Result<LValue, Unwind> {
+        match *p {
+            Procedure::Sum => {
+                let numbers = number_arguments(p, args)?;
+                Ok(LValue::NumericalValue(numbers.iter().fold(0.0, |sum, x| sum + x)))
+            }
+            Procedure::Product => {
+                let numbers = number_arguments(p, args)?;
+                Ok(LValue::NumericalValue(numbers.iter().product()))
+            }
+            Procedure::Difference => {
+                let numbers = number_arguments(p, args)?;
+                if numbers.len() == 1 {
+                    // Only one number was given, find the additive inverse.
+                    return Ok(LValue::NumericalValue(-numbers[0]));
+                }
+                let difference = numbers[1..].iter().fold(numbers[0], |d, x| d - x);
+                Ok(LValue::NumericalValue(difference))
+            }
+            Procedure::Division => {
+                let mut numbers = number_arguments(p, args)?;
+                if numbers.len() == 1 {
+                    // Only one number was given, find the inverse.
+                    numbers.insert(0, 1.0);
+                }
+                // All numbers are floats, so integers stand in for exact numbers: dividing
+                // them by zero is an error, while any other operand gives an infinity or a
+                // NaN. 1.0 reads as the integer 1, so (/ 1.0 0.0) is an error as well.
+                let exact = numbers.iter().all(|x| x.fract() == 0.0);
+                let mut ratio = numbers[0];
+                for x in &numbers[1..] {
+                    if exact && *x == 0.0 {
+                        return Err(Unwind::Error("'/' can't divide by zero.".to_string()));
+                    }
+                    ratio /= x;
+                }
+                Ok(LValue::NumericalValue(ratio))
+            }
+            Procedure::Equal | Procedure::Less | Procedure::Greater | Procedure::LessEqual |
+            Procedure::GreaterEqual => {
+                // NaN is unordered, so any comparison involving it is false.
+                let numbers = number_arguments(p, args)?;
+                Ok(LValue::BooleanValue(numbers.windows(2).all(|w| {
+                    w[0].partial_cmp(&w[1]).is_some_and(|ord| ordering_holds(p, ord))
+                })))
+            }
+            Procedure::StringEqual | Procedure::StringLess | Procedure::StringGreater |
+            Procedure::StringLessEqual | Procedure::StringGreaterEqual => {
+                let strings = (0..args.len())
+                    .map(|i| string_argument(p, args, i))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(LValue::BooleanValue(strings.windows(2).all(|w| {
+                    ordering_holds(p, w[0].borrow().as_str().cmp(w[1].borrow().as_str()))
+                })))
+            }
+            Procedure::BooleanEqual => {
+                let mut booleans = Vec::new();
+                for (i, value) in args.iter().enumerate() {
+                    match *value {
+                        LValue::BooleanValue(b) => booleans.push(b),
+                        ref other => return Err(wrong_type(p, i, "a boolean", other)),
+                    }
+                }
+                Ok(LValue::BooleanValue(booleans.windows(2).all(|w| w[0] == w[1])))
+            }
+            Procedure::CharEqual | Procedure::CharLess | Procedure::CharGreater |
+            Procedure::CharLessEqual | Procedure::CharGreaterEqual | Procedure::CharEqualCi |
+            Procedure::CharLessCi | Procedure::CharGreaterCi | Procedure::CharLessEqualCi |
+            Procedure::CharGreaterEqualCi => {
+                let fold = matches!(*p,
+                                    Procedure::CharEqualCi | Procedure::CharLessCi |
+                                    Procedure::CharGreaterCi | Procedure::CharLessEqualCi |
+                                    Procedure::CharGreaterEqualCi);
+                let mut chars = Vec::new();
+                for i in 0..args.len() {
+                    let c = char_argument(p, args, i)?;
+                    chars.push(if fold { character::foldcase(c) } else { c });
+                }
+                let holds = chars.windows(2).all(|w| ordering_holds(p, w[0].cmp(&w[1])));
+                Ok(LValue::BooleanValue(holds))
+            }
+            Procedure::CharToInteger => {
+                Ok(LValue::NumericalValue(char_argument(p, args, 0)? as u32 as f64))
+            }
+            Procedure::IntegerToChar => {
+                let code = index_argument(p, args, 0)?;
+                match ::std::char::from_u32(code as u32) {
+                    Some(c) if code <= 0x10FFFF => Ok(LValue::Character(c)),
+                    _ => Err(wrong_type(p, 0, "a Unicode scalar value", &args[0])),
+                }
+            }
+            Procedure::IsCharAlphabetic | Procedure::IsCharNumeric | Procedure::IsCharWhitespace |
+            Procedure::IsCharUpperCase | Procedure::IsCharLowerCase => {
+                let c = char_argument(p, args, 0)?;
+                Ok(LValue::BooleanValue(match *p {
+                    Procedure::IsCharAlphabetic => c.is_alphabetic(),
+                    Procedure::IsCharNumeric => character::digit_value(c).is_some(),
+                    Procedure::IsCharWhitespace => c.is_whitespace(),
+                    Procedure::IsCharUpperCase => c.is_uppercase(),
+                    _ => c.is_lowercase(),
+                }))
+            }
+            Procedure::DigitValue => {
+                match character::digit_value(char_argument(p, args, 0)?) {
+                    Some(d) => Ok(LValue::NumericalValue(d as f64)),
+                    None => Ok(LValue::BooleanValue(false)),
+                }
+            }
+            Procedure::CharUpcase | Procedure::CharDowncase | Procedure::CharFoldcase => {
+                let c = char_argument(p, args, 0)?;
+                Ok(LValue::Character(match *p {
+                    Procedure::CharUpcase => character::upcase(c),
+                    Procedure::CharDowncase => character::downcase(c),
+                    _ => character::foldcase(c),
+                }))
+            }
+            Procedure::MakeString => {
+                let k = index_argument(p, args, 0)?;
+                let c = if args.len() == 2 { char_argument(p, args, 1)? } else { ' ' };
+                Ok(LValue::string(iter::repeat_n(c, k).collect()))
+            }
+            Procedure::StringFromChars => {
+                let chars = (0..args.len())
+                    .map(|i| char_argument(p, args, i))
+                    .collect::<Result<String, _>>()?;
+                Ok(LValue::string(chars))
+            }
+            Procedure::StringLength => {
+                let length = string_argument(p, args, 0)?.borrow().chars().count();
+                Ok(LValue::NumericalValue(length as f64))
+            }
+            Procedure::StringRef => {
+                let chars = string_chars(p, args, 0)?;
+                let k = element_index(p, args, 1, chars.len())?;
+                Ok(LValue::Character(chars[k]))
+            }
+            Procedure::Substring | Procedure::StringCopy => {
+                let chars = string_chars(p, args, 0)?;
+                let (start, end) = range_arguments(p, args, 1, chars.len())?;
+                Ok(LValue::string(chars[start..end].iter().collect()))
+            }
+            Procedure::StringAppend => {
+                let mut text = String::new();
+                for i in 0..args.len() {
+                    text.push_str(&string_argument(p, args, i)?.borrow());
+                }
+                Ok(LValue::string(text))
+            }
+            Procedure::StringSet | Procedure::StringFill => {
+                let s = string_argument(p, args, 0)?;
+                check_mutable(p, args, 0, &s)?;
+                let mut chars: Vec<char> = s.borrow().chars().collect();
+                let (start, end) = if let Procedure::StringSet = *p {
+                    let k = element_index(p, args, 1, chars.len())?;
+                    (k, k + 1)
+                } else {
+                    range_arguments(p, args, 2, chars.len())?
+                };
+                let c = char_argument(p, args, if let Procedure::StringSet = *p { 2 } else { 1 })?;
+                for slot in &mut chars[start..end] {
+                    *slot = c;
+                }
+                *s.borrow_mut() = chars.into_iter().collect();
+                Ok(LValue::Undefined)
+            }
+            Procedure::StringToList => {
+                let chars = string_chars(p, args, 0)?;
+                let (start, end) = range_arguments(p, args, 1, chars.len())?;
+                Ok(LValue::list(chars[start..end].iter().cloned().map(LValue::Character).collect()))
+            }
+            Procedure::ListToString => {
+                let items = match args[0].list_items() {
+                    Some(items) => items,
+                    None => return Err(wrong_type(p, 0, "a list of characters", &args[0])),
+                };
+                let mut text = String::new();
+                for item in items {
+                    match item {
+                        LValue::Character(c) => text.push(c),
+                        _ => return Err(wrong_type(p, 0, "a list of characters", &args[0])),
+                    }
+                }
+                Ok(LValue::string(text))
+            }
+            Procedure::StringUpcase | Procedure::StringDowncase | Procedure::StringFoldcase => {
+                let s = string_argument(p, args, 0)?;
+                let s = s.borrow();
+                Ok(LValue::string(match *p {
+                    Procedure::StringUpcase => s.to_uppercase(),
+                    Procedure::StringDowncase => s.to_lowercase(),
+                    _ => s.chars().map(character::foldcase).collect(),
+                }))
+            }
+            Procedure::StringMap | Procedure::StringForEach => {
+                let procedure = procedure_argument(p, args, 0)?;
+                let strings = (1..args.len())
+                    .map(|i| string_chars(p, args, i))
+                    .collect::<Result<Vec<_>, _>>()?;
+                // Like `map`, this stops at the end of the shortest string.
+                let length = strings.iter().map(|s| s.len()).min().unwrap_or(0);
+                let mut text = String::new();
+                for k in 0..length {
+                    let chars: Vec<LValue> =
+                        strings.iter().map(|s| LValue::Character(s[k])).collect();
+                    match self.evaluate_call(&procedure, &chars)? {
+                        LValue::Character(c) => text.push(c),
+                        _ if matches!(*p, Procedure::StringForEach) => {}
+                        other => {
+                            return Err(Unwind::Error(format!("The procedure given to \
+                                                              'string-map' must return \
+                                                              characters, but returned {}.",
+                                                             other)))
+                        }
+                    }
+                }
+                match *p {
+                    Procedure::StringMap => Ok(LValue::string(text)),
+                    _ => Ok(LValue::Undefined),
+                }
+            }
+            Procedure::StringIndex => {
+                let chars = string_chars(p, args, 0)?;
+                let (start, end) = range_arguments(p, args, 2, chars.len())?;
+                for (k, c) in chars.iter().enumerate().take(end).skip(start) {
+                    let found = match args[1] {
+                        LValue::Character(wanted) => *c == wanted,
+                        LValue::Procedure(ref pred) => {
+                            self.evaluate_call(pred, &[LValue::Character(*c)])?
+                                .to_boolean()
+                                .map_err(Unwind::Error)?
+                        }
+                        ref other => {
+                            return Err(wrong_type(p, 1, "a character or a predicate", other))
+                        }
+                    };
+                    if found {
+                        return Ok(LValue::NumericalValue(k as f64));
+                    }
+                }
+                Ok(LValue::BooleanValue(false))
+            }
+            Procedure::StringSearchForward => {
+                let pattern = string_chars(p, args, 0)?;
+                let chars = string_chars(p, args, 1)?;
+                let start = element_index(p, args, 2, chars.len() + 1)?;
+                let position = if pattern.is_empty() {
+                    Some(start)
+                } else {
+                    chars[start..]
+                        .windows(pattern.len())
+                        .position(|w| w == &pattern[..])
+                        .map(|k| k + start)
+                };
+                match position {
+                    Some(k) => Ok(LValue::NumericalValue(k as f64)),
+                    None => Ok(LValue::BooleanValue(false)),
+                }
+            }
+            Procedure::StringSplit => {
+                let s = string_argument(p, args, 0)?;
+                let s = s.borrow();
+                let fields: Vec<&str> = match args[1] {
+                    LValue::Character(c) => s.split(c).collect(),
+                    LValue::StringValue(ref d) if !d.borrow().is_empty() => {
+                        s.split(d.borrow().as_str()).collect()
+                    }
+                    ref other => {
+                        return Err(wrong_type(p, 1, "a character or a non-empty string", other))
+                    }
+                };
+                let fields = fields.into_iter().map(|f| LValue::string(f.to_string())).collect();
+                Ok(LValue::list(fields))
+            }
+            Procedure::StringJoin => {
+                let items = match args[0].list_items() {
+                    Some(items) => items,
+                    None => return Err(wrong_type(p, 0, "a list of strings", &args[0])),
+                };
+                let mut strings = Vec::new();
+                for item in items {
+                    match item {
+                        LValue::StringValue(s) => strings.push(s.borrow().clone()),
+                        _ => return Err(wrong_type(p, 0, "a list of strings", &args[0])),
+                    }
+                }
+                let delimiter = if args.len() == 2 {
+                    string_argument(p, args, 1)?.borrow().clone()
+                } else {
+                    " ".to_string()
+                };
+                Ok(LValue::string(strings.join(&delimiter)))
+            }
+            Procedure::StringTrim | Procedure::StringTrimRight | Procedure::StringTrimBoth => {
+                let s = string_argument(p, args, 0)?;
+                let s = s.borrow();
+                Ok(LValue::string(match *p {
+                        Procedure::StringTrim => s.trim_start(),
+                        Procedure::StringTrimRight => s.trim_end(),
+                        _ => s.trim(),
+                    }
+                    .to_string()))
+            }
+            Procedure::StringPad | Procedure::StringPadRight => {
+                let chars = string_chars(p, args, 0)?;
+                let n = index_argument(p, args, 1)?;
+                let c = if args.len() == 3 { char_argument(p, args, 2)? } else { ' ' };
+                let padding = iter::repeat_n(c, n.saturating_sub(chars.len()));
+                // Strings longer than `n` lose characters on the side the padding would go.
+                Ok(LValue::string(if let Procedure::StringPad = *p {
+                    padding.chain(chars[chars.len().saturating_sub(n)..].iter().cloned()).collect()
+                } else {
+                    chars[..n.min(chars.len())].iter().cloned().chain(padding).collect()
+                }))
+            }
+            Procedure::MakeVector => {
+                let k = index_argument(p, args, 0)?;
+                let fill = args.get(1).cloned().unwrap_or(LValue::BooleanValue(false));
+                Ok(LValue::vector(vec![fill; k]))
+            }
+            Procedure::Vector => Ok(LValue::vector(args.to_vec())),
+            Procedure::VectorLength => {
+                let length = vector_argument(p, args, 0)?.borrow().len();
+                Ok(LValue::NumericalValue(length as f64))
+            }
+            Procedure::VectorRef => {
+                let v = vector_argument(p, args, 0)?;
+                let v = v.borrow();
+                let k = element_index(p, args, 1, v.len())?;
+                Ok(v[k].clone())
+            }
+            Procedure::VectorSet => {
+                let v = vector_argument(p, args, 0)?;
+                check_mutable(p, args, 0, &v)?;
+                let k = element_index(p, args, 1, v.borrow().len())?;
+                v.borrow_mut()[k] = args[2].clone();
+                Ok(LValue::Undefined)
+            }
+            Procedure::VectorToList | Procedure::VectorCopy => {
+                let v = vector_argument(p, args, 0)?;
+                let v = v.borrow();
+                let (start, end) = range_arguments(p, args, 1, v.len())?;
+                let items = v[start..end].to_vec();
+                Ok(match *p {
+                    Procedure::VectorToList => LValue::list(items),
+                    _ => LValue::vector(items),
+                })
+            }
+            Procedure::ListToVector => {
+                match args[0].list_items() {
+                    Some(items) => Ok(LValue::vector(items)),
+                    None => Err(wrong_type(p, 0, "a list", &args[0])),
+                }
+            }
+            Procedure::VectorFill => {
+                let v = vector_argument(p, args, 0)?;
+                check_mutable(p, args, 0, &v)?;
+                let (start, end) = range_arguments(p, args, 2, v.borrow().len())?;
+                for slot in &mut v.borrow_mut()[start..end] {
+                    *slot = args[1].clone();
+                }
+                Ok(LValue::Undefined)
+            }
+            Procedure::VectorCopyInto => {
+                let to = vector_argument(p, args, 0)?;
+                let from = vector_argument(p, args, 2)?;
+                copy_range(p, args, &to, &from)
+            }
+            Procedure::VectorAppend => {
+                let mut items = Vec::new();
+                for i in 0..args.len() {
+                    items.extend(vector_argument(p, args, i)?.borrow().iter().cloned());
+                }
+                Ok(LValue::vector(items))
+            }
+            Procedure::VectorMap | Procedure::VectorForEach => {
+                let procedure = procedure_argument(p, args, 0)?;
+                // The procedure may modify the vectors, so it works on copies of them.
+                let vectors = (1..args.len())
+                    .map(|i| vector_argument(p, args, i).map(|v| v.borrow().clone()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let length = vectors.iter().map(|v| v.len()).min().unwrap_or(0);
+                let mut results = Vec::new();
+                for k in 0..length {
+                    let items: Vec<LValue> = vectors.iter().map(|v| v[k].clone()).collect();
+                    results.push(self.evaluate_call(&procedure, &items)?);
+                }
+                match *p {
+                    Procedure::VectorMap => Ok(LValue::vector(results)),
+                    _ => Ok(LValue::Undefined),
+                }
+            }
+            Procedure::MakeBytevector => {
+                let k = index_argument(p, args, 0)?;
+                let fill = if args.len() == 2 { byte_argument(p, args, 1)? } else { 0 };
+                Ok(LValue::bytevector(vec![fill; k]))
+            }
+            Procedure::Bytevector => {
+                let bytes = (0..args.len())
+                    .map(|i| byte_argument(p, args, i))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(LValue::bytevector(bytes))
+            }
+            Procedure::BytevectorLength => {
+                let length = bytevector_argument(p, args, 0)?.borrow().len();
+                Ok(LValue::NumericalValue(length as f64))
+            }
+            Procedure::BytevectorU8Ref => {
+                let v = bytevector_argument(p, args, 0)?;
+                let v = v.borrow();
+                let k = element_index(p, args, 1, v.len())?;
+                Ok(LValue::NumericalValue(v[k] as f64))
+            }
+            Procedure::BytevectorU8Set => {
+                let v = bytevector_argument(p, args, 0)?;
+                check_mutable(p, args, 0, &v)?;
+                let k = element_index(p, args, 1, v.borrow().len())?;
+                v.borrow_mut()[k] = byte_argument(p, args, 2)?;
+                Ok(LValue::Undefined)
+            }
+            Procedure::BytevectorCopy => {
+                let v = bytevector_argument(p, args, 0)?;
+                let v = v.borrow();
+                let (start, end) = range_arguments(p, args, 1, v.len())?;
+                Ok(LValue::bytevector(v[start..end].to_vec()))
+            }
+            Procedure::BytevectorCopyInto => {
+                let to = bytevector_argument(p, args, 0)?;
+                let from = bytevector_argument(p, args, 2)?;
+                copy_range(p, args, &to, &from)
+            }
+            Procedure::BytevectorAppend => {
+                let mut bytes = Vec::new();
+                for i in 0..args.len() {
+                    bytes.extend_from_slice(&bytevector_argument(p, args, i)?.borrow());
+                }
+                Ok(LValue::bytevector(bytes))
+            }
+            Procedure::Utf8ToString => {
+                let v = bytevector_argument(p, args, 0)?;
+                let v = v.borrow();
+                let (start, end) = range_arguments(p, args, 1, v.len())?;
+                match String::from_utf8(v[start..end].to_vec()) {
+                    Ok(text) => Ok(LValue::string(text)),
+                    Err(_) => Err(wrong_type(p, 0, "a bytevector of UTF-8 text", &args[0])),
+                }
+            }
+            Procedure::StringToUtf8 => {
+                let chars = string_chars(p, args, 0)?;
+                let (start, end) = range_arguments(p, args, 1, chars.len())?;
+                let text: String = chars[start..end].iter().collect();
+                Ok(LValue::bytevector(text.into_bytes()))
+            }
+            Procedure::OpenInputBytevector => {
+                let port = Port::open_input_bytevector(&bytevector_argument(p, args, 0)?.borrow());
+                Ok(LValue::Port(Rc::new(RefCell::new(port))))
+            }
+            Procedure::OpenOutputBytevector => {
+                Ok(LValue::Port(Rc::new(RefCell::new(Port::open_output_bytevector()))))
+            }
+            Procedure::GetOutputBytevector => {
+                let bytes = port_argument(p, args, 0)?.borrow().output_bytes();
+                match bytes {
+                    Some(bytes) => Ok(LValue::bytevector(bytes)),
+                    None => Err(wrong_type(p, 0, "a bytevector output port", &args[0])),
+                }
+            }
+            Procedure::ReadU8 | Procedure::PeekU8 | Procedure::IsU8Ready => {
+                let port = self.binary_input_port(p, args, 0)?;
+                let mut port = port.borrow_mut();
+                let byte = match *p {
+                    Procedure::ReadU8 => port.read_u8(),
+                    Procedure::PeekU8 => port.peek_u8(),
+                    _ => return port.u8_ready().map(LValue::BooleanValue).map_err(Unwind::Error),
+                };
+                match byte.map_err(Unwind::Error)? {
+                    Some(b) => Ok(LValue::NumericalValue(b as f64)),
+                    None => Ok(LValue::Eof),
+                }
+            }
+            Procedure::ReadBytevector => {
+                let k = index_argument(p, args, 0)?;
+                let port = self.binary_input_port(p, args, 1)?;
+                let bytes = port.borrow_mut().read_bytes(k).map_err(Unwind::Error)?;
+                Ok(bytes.map(LValue::bytevector).unwrap_or(LValue::Eof))
+            }
+            Procedure::WriteU8 => {
+                let byte = byte_argument(p, args, 0)?;
+                let port = self.binary_output_port(p, args, 1)?;
+                port.borrow_mut().write_bytes(&[byte]).map_err(Unwind::Error)?;
+                Ok(LValue::Undefined)
+            }
+            Procedure::WriteBytevector => {
+                let v = bytevector_argument(p, args, 0)?;
+                let (start, end) = range_arguments(p, args, 2, v.borrow().len())?;
+                let port = self.binary_output_port(p, args, 1)?;
+                port.borrow_mut().write_bytes(&v.borrow()[start..end]).map_err(Unwind::Error)?;
+                Ok(LValue::Undefined)
+            }
+            Procedure::NumericVector(t, op) => {
+                match op {
+                    NumericVectorOp::IsVector => {
+                        Ok(LValue::BooleanValue(numeric_vector_argument(p, args, 0, t).is_ok()))
+                    }
+                    NumericVectorOp::Make => {
+                        let k = index_argument(p, args, 0)?;
+                        let fill = match args.len() {
+                            2 => element_argument(p, args, 1, t)?,
+                            _ => 0.0,
+                        };
+                        Ok(LValue::numeric_vector(t, &vec![fill; k]))
+                    }
+                    NumericVectorOp::Construct => {
+                        let values = (0..args.len())
+                            .map(|i| element_argument(p, args, i, t))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        Ok(LValue::numeric_vector(t, &values))
+                    }
+                    NumericVectorOp::Length => {
+                        let length = numeric_vector_argument(p, args, 0, t)?.len();
+                        Ok(LValue::NumericalValue(length as f64))
+                    }
+                    NumericVectorOp::Ref => {
+                        let v = numeric_vector_argument(p, args, 0, t)?;
+                        let k = element_index(p, args, 1, v.len())?;
+                        Ok(LValue::NumericalValue(v.get(k)))
+                    }
+                    NumericVectorOp::Set => {
+                        let v = numeric_vector_argument(p, args, 0, t)?;
+                        match v {
+                            TypedVector::Bytes(ref bytes) => check_mutable(p, args, 0, bytes)?,
+                            TypedVector::Numbers(ref numbers) => {
+                                check_mutable(p, args, 0, numbers)?
                             }
                         }
-                        Err(s) => {
-                            println!("Syntax error: {}", s);
-                            break;
+                        let k = element_index(p, args, 1, v.len())?;
+                        v.set(k, element_argument(p, args, 2, t)?);
+                        Ok(LValue::Undefined)
+                    }
+                    NumericVectorOp::ToList | NumericVectorOp::ToVector => {
+                        let values = numeric_vector_argument(p, args, 0, t)?.to_f64s();
+                        let items = values.into_iter().map(LValue::NumericalValue).collect();
+                        Ok(match op {
+                            NumericVectorOp::ToList => LValue::list(items),
+                            _ => LValue::vector(items),
+                        })
+                    }
+                    NumericVectorOp::FromList | NumericVectorOp::FromVector => {
+                        let items = match (op, &args[0]) {
+                            (NumericVectorOp::FromList, value) => value.list_items(),
+                            (_, LValue::Vector(v)) => Some(v.borrow().clone()),
+                            _ => None,
+                        };
+                        let items = match items {
+                            Some(items) => items,
+                            None if op == NumericVectorOp::FromList => {
+                                return Err(wrong_type(p, 0, "a list", &args[0]))
+                            }
+                            None => return Err(wrong_type(p, 0, "a vector", &args[0])),
+                        };
+                        let mut values = Vec::new();
+                        for item in items {
+                            match item {
+                                LValue::NumericalValue(x) if t.accepts(x) => values.push(x),
+                                other => {
+                                    return Err(Unwind::Error(format!("'{}' expects elements \
+                                                                      that are each {}, but \
+                                                                      got {}.",
+                                                                     p.name(),
+                                                                     t.description(),
+                                                                     other)))
+                                }
+                            }
                         }
+                        Ok(LValue::numeric_vector(t, &values))
                     }
                 }
             }
-            _ => println!("Fatal error."),
-        }
-    }
-
-    pub fn initialize(&mut self) {
-        // Add the basic functions.
-        let mut basic_map: HashMap<String, LValue> = HashMap::new();
-        basic_map.insert("+".to_string(), LValue::Procedure(Procedure::Sum));
-        basic_map.insert("-".to_string(), LValue::Procedure(Procedure::Difference));
-        basic_map.insert("*".to_string(), LValue::Procedure(Procedure::Product));
-        basic_map.insert("/".to_string(), LValue::Procedure(Procedure::Division));
-        basic_map.insert("=".to_string(), LValue::Procedure(Procedure::Equal));
-        basic_map.insert("<".to_string(), LValue::Procedure(Procedure::Less));
-        basic_map.insert(">".to_string(), LValue::Procedure(Procedure::Greater));
-        basic_map.insert("and".to_string(), LValue::Procedure(Procedure::And));
-        basic_map.insert("or".to_string(), LValue::Procedure(Procedure::Or));
-        basic_map.insert("not".to_string(), LValue::Procedure(Procedure::Not));
-        self.stack.push(basic_map);
-    }
-
-    fn find_identifier(&self, name: &str) -> Option<&LValue> {
-        let stack_ref = &self.stack;
-        for name_stack in stack_ref.into_iter().rev() {
-            match name_stack.get(name) {
-                Some(lres) => return Some(lres),
-                None => continue,
+            Procedure::MakeHashTable => {
+                let equivalence = match args.first() {
+                    None | Some(LValue::Procedure(Procedure::IsEqual)) => Equivalence::Equal,
+                    Some(LValue::Procedure(Procedure::IsEqv)) |
+                    Some(LValue::Procedure(Procedure::IsEq)) => Equivalence::Eqv,
+                    Some(LValue::Procedure(Procedure::StringEqual)) => Equivalence::StringEqual,
+                    Some(other) => {
+                        return Err(wrong_type(p, 0, "equal?, eqv?, eq? or string=?", other))
+                    }
+                };
+                Ok(LValue::HashTable(Rc::new(RefCell::new(HashTable::new(equivalence)))))
             }
-        }
-        None
-    }
-
-    fn evaluate_call(&mut self, p: &Procedure, args: &Vec<LValue>) -> Result<LValue, String> {
-        match *p {
-            Procedure::Sum => {
-                let mut sum: f64 = 0.0;
-                for value in args {
-                    if let &LValue::NumericalValue(ref x) = value {
-                        sum += *x;
+            Procedure::HashTableRef => self.hash_table_ref(p, args, 2),
+            Procedure::HashTableRefDefault => {
+                let table = hash_table_argument(p, args, 0)?;
+                let key = key_argument(p, &table, args, 1)?;
+                let value = table.borrow().get(&key);
+                Ok(value.unwrap_or_else(|| args[2].clone()))
+            }
+            Procedure::HashTableSet => {
+                let table = hash_table_argument(p, args, 0)?;
+                let key = key_argument(p, &table, args, 1)?;
+                table.borrow_mut().insert(key, args[2].clone());
+                Ok(LValue::Undefined)
+            }
+            Procedure::HashTableDelete => {
+                let table = hash_table_argument(p, args, 0)?;
+                let mut deleted = 0;
+                for i in 1..args.len() {
+                    let key = key_argument(p, &table, args, i)?;
+                    if table.borrow_mut().remove(&key) {
+                        deleted += 1;
                     }
                 }
-                Ok(LValue::NumericalValue(sum))
+                Ok(LValue::NumericalValue(deleted as f64))
             }
-            Procedure::Product => {
-                let mut product: f64 = 1.0;
-                for value in args {
-                    if let &LValue::NumericalValue(ref x) = value {
-                        product *= *x;
+            Procedure::HashTableContains => {
+                let table = hash_table_argument(p, args, 0)?;
+                let key = key_argument(p, &table, args, 1)?;
+                let found = table.borrow().get(&key).is_some();
+                Ok(LValue::BooleanValue(found))
+            }
+            Procedure::HashTableUpdate | Procedure::HashTableUpdateDefault => {
+                let table = hash_table_argument(p, args, 0)?;
+                let key = key_argument(p, &table, args, 1)?;
+                let updater = procedure_argument(p, args, 2)?;
+                let value = match *p {
+                    Procedure::HashTableUpdate => self.hash_table_ref(p, args, 3)?,
+                    _ => table.borrow().get(&key).unwrap_or_else(|| args[3].clone()),
+                };
+                let value = self.evaluate_call(&updater, &[value])?;
+                table.borrow_mut().insert(key, value);
+                Ok(LValue::Undefined)
+            }
+            Procedure::HashTableSize => {
+                let size = hash_table_argument(p, args, 0)?.borrow().len();
+                Ok(LValue::NumericalValue(size as f64))
+            }
+            Procedure::HashTableKeys | Procedure::HashTableValues | Procedure::HashTableToAlist => {
+                let entries = hash_table_argument(p, args, 0)?.borrow().entries();
+                Ok(LValue::list(entries.into_iter()
+                    .map(|(key, value)| match *p {
+                        Procedure::HashTableKeys => key,
+                        Procedure::HashTableValues => value,
+                        _ => LValue::cons(key, value),
+                    })
+                    .collect()))
+            }
+            Procedure::HashTableWalk => {
+                // The procedure may modify the table, so it goes through a copy of the entries.
+                let entries = hash_table_argument(p, args, 0)?.borrow().entries();
+                let procedure = procedure_argument(p, args, 1)?;
+                for (key, value) in entries {
+                    self.evaluate_call(&procedure, &[key, value])?;
+                }
+                Ok(LValue::Undefined)
+            }
+            Procedure::Hash | Procedure::StringHash => {
+                if let Procedure::StringHash = *p {
+                    string_argument(p, args, 0)?;
+                }
+                let hash = hash_equal(&args[0]);
+                if args.len() == 2 {
+                    let bound = index_argument(p, args, 1)?;
+                    if bound == 0 {
+                        return Err(wrong_type(p, 1, "a positive integer", &args[1]));
                     }
+                    Ok(LValue::NumericalValue((hash % bound as u64) as f64))
+                } else {
+                    // Numbers only hold integers exactly up to 53 bits.
+                    Ok(LValue::NumericalValue((hash >> 11) as f64))
                 }
-                Ok(LValue::NumericalValue(product))
             }
-            Procedure::Difference => {
-                let mut difference: f64 = 0.0;
-                let mut index = 0;
-                for value in args {
-                    if let &LValue::NumericalValue(ref x) = value {
-                        if index == 0 {
-                            difference += *x;
+            Procedure::And | Procedure::Or | Procedure::Not => {
+                let mut values = Vec::new();
+                for (i, v) in args.iter().enumerate() {
+                    values.push(v.to_boolean().map_err(|_| wrong_type(p, i, "a truth value", v))?);
+                }
+                Ok(LValue::BooleanValue(match *p {
+                    Procedure::And => values.iter().all(|b| *b),
+                    Procedure::Or => values.iter().any(|b| *b),
+                    _ => !values[0],
+                }))
+            }
+            Procedure::Cons => Ok(LValue::cons(args[0].clone(), args[1].clone())),
+            Procedure::Car | Procedure::Cdr => {
+                match args[0] {
+                    LValue::Pair(ref pair) => {
+                        let pair = pair.borrow();
+                        Ok(if let Procedure::Car = *p {
+                            pair.car.clone()
+                        } else {
+                            pair.cdr.clone()
+                        })
+                    }
+                    ref other => Err(wrong_type(p, 0, "a pair", other)),
+                }
+            }
+            Procedure::SetCar | Procedure::SetCdr => {
+                match args[0] {
+                    LValue::Pair(ref pair) => {
+                        let mut pair = pair.borrow_mut();
+                        if let Procedure::SetCar = *p {
+                            pair.car = args[1].clone();
                         } else {
-                            difference -= *x;
+                            pair.cdr = args[1].clone();
                         }
-                        index += 1;
+                        Ok(LValue::Undefined)
                     }
+                    ref other => Err(wrong_type(p, 0, "a pair", other)),
                 }
-                if index == 1 {
-                    // Only one number was given, find the additive inverse.
-                    difference *= -1.0;
+            }
+            Procedure::IsEq | Procedure::IsEqv | Procedure::IsEqual => {
+                Ok(LValue::BooleanValue(match *p {
+                    Procedure::IsEqual => args[0].equal(&args[1]),
+                    _ => args[0].eqv(&args[1]),
+                }))
+            }
+            Procedure::List => Ok(LValue::list(args.to_vec())),
+            Procedure::IsErrorObject => {
+                match args[0] {
+                    LValue::Condition(_) => Ok(LValue::BooleanValue(true)),
+                    _ => Ok(LValue::BooleanValue(false)),
                 }
-                Ok(LValue::NumericalValue(difference))
             }
-            Procedure::Division => {
-                let mut ratio: f64 = 1.0;
-                let mut index = 0;
-                for value in args {
-                    if let &LValue::NumericalValue(ref x) = value {
-                        if index == 0 {
-                            ratio *= *x;
+            Procedure::ErrorObjectMessage | Procedure::ErrorObjectIrritants => {
+                match args[0] {
+                    LValue::Condition(ref c) => {
+                        Ok(if let Procedure::ErrorObjectMessage = *p {
+                            LValue::string(c.message.clone())
                         } else {
-                            ratio /= *x;
-                        }
-                        index += 1;
+                            LValue::list(c.irritants.clone())
+                        })
                     }
+                    ref other => Err(wrong_type(p, 0, "an error object", other)),
                 }
-                if index == 1 {
-                    // Only one number was given, find the inverse.
-                    ratio = 1.0 / ratio;
+            }
+            Procedure::ComputeRestarts => {
+                let restarts = self.restarts.iter().rev().cloned().map(LValue::Restart).collect();
+                Ok(LValue::list(restarts))
+            }
+            Procedure::FindRestart => {
+                restart_designator(p, args)?;
+                match self.find_restart(&args[0]) {
+                    Some(r) => Ok(LValue::Restart(r)),
+                    None => Ok(LValue::BooleanValue(false)),
                 }
-                Ok(LValue::NumericalValue(ratio))
             }
-            Procedure::Equal => {
-                if args.len() != 2 {
-                    return Err("Equality test needs two arguments.".to_string());
+            Procedure::InvokeRestart => {
+                restart_designator(p, args)?;
+                let restart = match self.find_restart(&args[0]) {
+                    Some(r) => r,
+                    None => return Err(Unwind::Error(format!("No active restart {}.", args[0]))),
+                };
+                if args.len() - 1 != restart.arity {
+                    return Err(Unwind::Error(format!("The restart {} takes {} argument(s).",
+                                                     restart.name,
+                                                     restart.arity)));
                 }
-                match args[0].compare(&args[1]) {
-                    Ok(ord) => {
-                        match ord {
-                            Ordering::Equal => Ok(LValue::BooleanValue(true)),
-                            _ => Ok(LValue::BooleanValue(false)),
-                        }
+                Err(Unwind::Escape(restart.id, args[1..].to_vec()))
+            }
+            Procedure::RestartName => {
+                match args[0] {
+                    LValue::Restart(ref r) => Ok(LValue::Symbol(r.name.clone())),
+                    ref other => Err(wrong_type(p, 0, "a restart", other)),
+                }
+            }
+            Procedure::Values => Ok(LValue::from_values(args.to_vec())),
+            Procedure::MakePromise => {
+                match args[0] {
+                    LValue::Promise(_) => Ok(args[0].clone()),
+                    ref value => {
+                        let promise = Promise::new(PromiseState::Forced(value.clone()));
+                        Ok(LValue::Promise(promise))
                     }
-                    Err(s) => Err(s),
                 }
             }
-            Procedure::Less => {
-                if args.len() != 2 {
-                    return Err("Comparison test needs two arguments.".to_string());
+            Procedure::IsPromise | Procedure::IsNull | Procedure::IsPair |
+            Procedure::IsNumber | Procedure::IsComplex | Procedure::IsReal |
+            Procedure::IsRational | Procedure::IsInteger | Procedure::IsString |
+            Procedure::IsBoolean | Procedure::IsSymbol | Procedure::IsApplicable |
+            Procedure::IsList | Procedure::IsPort | Procedure::IsInputPort |
+            Procedure::IsOutputPort | Procedure::IsTextualPort | Procedure::IsEnvironment |
+            Procedure::IsRestart | Procedure::IsChar | Procedure::IsVector |
+            Procedure::IsBytevector | Procedure::IsBinaryPort | Procedure::IsHashTable => {
+                Ok(LValue::BooleanValue(has_type(p, &args[0])))
+            }
+            Procedure::TypeOf => Ok(LValue::Symbol(args[0].type_name().to_string())),
+            Procedure::MakeParameter => {
+                let converter = if args.len() == 2 {
+                    Some(procedure_argument(p, args, 1)?)
+                } else {
+                    None
+                };
+                let value = match converter {
+                    Some(ref c) => self.evaluate_call(c, &[args[0].clone()])?,
+                    None => args[0].clone(),
+                };
+                Ok(LValue::Procedure(Procedure::Parameter(Rc::new(Parameter {
+                    value,
+                    converter,
+                }))))
+            }
+            Procedure::Parameter(ref parameter) => Ok(self.parameter_value(parameter)),
+            Procedure::Display | Procedure::Write => {
+                let text = match *p {
+                    Procedure::Display => Displayed(&args[0]).to_string(),
+                    _ => args[0].to_string(),
+                };
+                let port = self.output_port(p, args, 1)?;
+                port.borrow_mut().write_str(&text).map_err(Unwind::Error)?;
+                Ok(LValue::Undefined)
+            }
+            Procedure::WriteString => {
+                let chars = string_chars(p, args, 0)?;
+                let (start, end) = range_arguments(p, args, 2, chars.len())?;
+                let text: String = chars[start..end].iter().collect();
+                let port = self.output_port(p, args, 1)?;
+                port.borrow_mut().write_str(&text).map_err(Unwind::Error)?;
+                Ok(LValue::Undefined)
+            }
+            Procedure::WriteChar => {
+                let c = char_argument(p, args, 0)?;
+                let port = self.output_port(p, args, 1)?;
+                port.borrow_mut().write_str(&c.to_string()).map_err(Unwind::Error)?;
+                Ok(LValue::Undefined)
+            }
+            Procedure::Newline | Procedure::FlushOutputPort => {
+                let port = self.output_port(p, args, 0)?;
+                let mut port = port.borrow_mut();
+                match *p {
+                    Procedure::Newline => port.write_str("\n"),
+                    _ => port.flush(),
+                }
+                .map_err(Unwind::Error)?;
+                Ok(LValue::Undefined)
+            }
+            Procedure::OpenInputFile | Procedure::OpenOutputFile | Procedure::OpenBinaryInputFile |
+            Procedure::OpenBinaryOutputFile => {
+                Ok(LValue::Port(Program::open_file(p, args)?))
+            }
+            Procedure::CallWithInputFile | Procedure::CallWithOutputFile |
+            Procedure::WithOutputToFile => {
+                let procedure = procedure_argument(p, args, 1)?;
+                let port = Program::open_file(p, args)?;
+                let result = if let Procedure::WithOutputToFile = *p {
+                    self.with_output_to(&port, &procedure)
+                } else {
+                    self.evaluate_call(&procedure, &[LValue::Port(port.clone())])
+                };
+                let closed = port.borrow_mut().close();
+                let result = result?;
+                closed.map_err(Unwind::Error)?;
+                Ok(result)
+            }
+            Procedure::ReadLine | Procedure::ReadChar | Procedure::PeekChar |
+            Procedure::IsCharReady => {
+                let port = self.input_port(p, args, 0)?;
+                let mut port = port.borrow_mut();
+                let value = match *p {
+                    Procedure::ReadLine => port.read_line().map(|l| l.map(LValue::string)),
+                    Procedure::ReadChar => port.read_char().map(|c| c.map(LValue::Character)),
+                    Procedure::PeekChar => port.peek_char().map(|c| c.map(LValue::Character)),
+                    _ => return port.char_ready().map(LValue::BooleanValue).map_err(Unwind::Error),
+                };
+                Ok(value.map_err(Unwind::Error)?.unwrap_or(LValue::Eof))
+            }
+            Procedure::ReadString => {
+                let k = index_argument(p, args, 0)?;
+                let port = self.input_port(p, args, 1)?;
+                let text = port.borrow_mut().read_string(k).map_err(Unwind::Error)?;
+                match text {
+                    Some(text) => Ok(LValue::string(text)),
+                    None => Ok(LValue::Eof),
                 }
-                match args[0].compare(&args[1]) {
-                    Ok(ord) => {
-                        match ord {
-                            Ordering::Less => Ok(LValue::BooleanValue(true)),
-                            _ => Ok(LValue::BooleanValue(false)),
+            }
+            Procedure::EofObject => Ok(LValue::Eof),
+            Procedure::IsEofObject | Procedure::IsFileError | Procedure::IsReadError => {
+                Ok(LValue::BooleanValue(match args[0] {
+                    LValue::Eof => matches!(*p, Procedure::IsEofObject),
+                    LValue::Condition(ref c) => {
+                        match *p {
+                            Procedure::IsFileError => c.kind == ConditionKind::File,
+                            Procedure::IsReadError => c.kind == ConditionKind::Read,
+                            _ => false,
                         }
                     }
-                    Err(s) => Err(s),
+                    _ => false,
+                }))
+            }
+            Procedure::ClosePort => {
+                port_argument(p, args, 0)?.borrow_mut().close().map_err(Unwind::Error)?;
+                Ok(LValue::Undefined)
+            }
+            Procedure::OpenInputString => {
+                let s = string_argument(p, args, 0)?;
+                let port = Port::open_input_string(&s.borrow());
+                Ok(LValue::Port(Rc::new(RefCell::new(port))))
+            }
+            Procedure::OpenOutputString => {
+                Ok(LValue::Port(Rc::new(RefCell::new(Port::open_output_string()))))
+            }
+            Procedure::GetOutputString => {
+                let text = port_argument(p, args, 0)?.borrow().output_string();
+                match text {
+                    Some(text) => Ok(LValue::string(text)),
+                    None => Err(wrong_type(p, 0, "a string output port", &args[0])),
+                }
+            }
+            Procedure::WithOutputToString => {
+                let thunk = procedure_argument(p, args, 0)?;
+                let port = Rc::new(RefCell::new(Port::open_output_string()));
+                self.with_output_to(&port, &thunk)?;
+                let text = port.borrow().output_string().unwrap();
+                Ok(LValue::string(text))
+            }
+            Procedure::Read => {
+                let port = self.input_port(p, args, 0)?;
+                let mut port = port.borrow_mut();
+                match ListNode::read(&mut port) {
+                    Ok(Some(datum)) => Ok(LValue::from_list(&datum)),
+                    Ok(None) => Ok(LValue::Eof),
+                    Err(s) => {
+                        let mut condition = Condition::read_error(s, Vec::new());
+                        condition.location = port.location();
+                        Err(Unwind::Signal(condition))
+                    }
                 }
             }
-            Procedure::Greater => {
-                if args.len() != 2 {
-                    return Err("Comparison test needs two arguments.".to_string());
+            Procedure::SchemeReportEnvironment | Procedure::NullEnvironment => {
+                match args[0] {
+                    LValue::NumericalValue(5.0) => {}
+                    ref other => return Err(wrong_type(p, 0, "the version number 5", other)),
                 }
-                match args[0].compare(&args[1]) {
-                    Ok(ord) => {
-                        match ord {
-                            Ordering::Greater => Ok(LValue::BooleanValue(true)),
-                            _ => Ok(LValue::BooleanValue(false)),
-                        }
+                // Special forms are recognized in any environment, so the null environment
+                // is just an empty one.
+                let parent = match *p {
+                    Procedure::SchemeReportEnvironment => Some(self.system.clone()),
+                    _ => None,
+                };
+                Ok(LValue::Environment(Frame::new(HashMap::new(), parent)))
+            }
+            Procedure::InteractionEnvironment => Ok(LValue::Environment(self.global.clone())),
+            Procedure::Environment => {
+                let mut bindings = HashMap::new();
+                for (i, spec) in args.iter().enumerate() {
+                    let set = spec.to_list()
+                        .and_then(|l| ImportSet::parse(&l))
+                        .map_err(|_| wrong_type(p, i, "an import set", spec))?;
+                    bindings.extend(self.import(&set)?);
+                }
+                Ok(LValue::Environment(Frame::new(bindings, None)))
+            }
+            Procedure::TheEnvironment => Ok(LValue::Environment(self.environment.clone())),
+            Procedure::IsEnvironmentBound | Procedure::EnvironmentBoundNames |
+            Procedure::EnvironmentLookup | Procedure::EnvironmentDefine |
+            Procedure::EnvironmentParent => {
+                let env = environment_argument(p, args, 0)?;
+                let name = if args.len() > 1 {
+                    symbol_argument(p, args, 1)?
+                } else {
+                    String::new()
+                };
+                match *p {
+                    Procedure::IsEnvironmentBound => {
+                        Ok(LValue::BooleanValue(Frame::lookup(&env, &name).is_some()))
+                    }
+                    Procedure::EnvironmentLookup => {
+                        Frame::lookup(&env, &name).ok_or_else(|| {
+                            Unwind::Error(format!("Undefined identifier '{}'.", name))
+                        })
+                    }
+                    Procedure::EnvironmentDefine => {
+                        env.borrow_mut().bindings.insert(name, args[2].clone());
+                        Ok(LValue::Undefined)
+                    }
+                    Procedure::EnvironmentBoundNames => {
+                        let mut names: Vec<String> = env.borrow().bindings.keys().cloned().collect();
+                        names.sort();
+                        Ok(LValue::list(names.into_iter().map(LValue::Symbol).collect()))
+                    }
+                    _ => {
+                        Ok(match env.borrow().parent {
+                            Some(ref parent) => LValue::Environment(parent.clone()),
+                            None => LValue::BooleanValue(false),
+                        })
                     }
-                    Err(s) => Err(s),
                 }
             }
-            Procedure::And => {
-                if args.len() < 2 {
-                    return Err("'And' requires at least two operands.".to_string());
+            Procedure::Load => {
+                let path = self.resolve_path(&string_argument(p, args, 0)?.borrow());
+                let environment = if args.len() == 2 {
+                    environment_argument(p, args, 1)?
+                } else {
+                    self.global.clone()
+                };
+                self.load_file(&path, environment, false)?;
+                Ok(LValue::Undefined)
+            }
+            Procedure::Features => {
+                Ok(LValue::list(features().into_iter().map(LValue::Symbol).collect()))
+            }
+            Procedure::UserDefined { .. } | Procedure::WithExceptionHandler | Procedure::Raise |
+            Procedure::RaiseContinuable | Procedure::Error | Procedure::DynamicWind |
+            Procedure::CallWithCurrentContinuation | Procedure::Continuation(_) |
+            Procedure::CallWithValues | Procedure::Force | Procedure::Eval => unreachable!(),
+        }
+    }
+
+    /// Runs the evaluator from `step` until a value is returned to the frames that were
+    /// already pending, or an unwind gets past them. Builtins that call back into Scheme
+    /// code start runs of their own.
+    fn run(&mut self, mut step: Step, top_level: bool) -> Result<LValue, Unwind> {
+        let base = self.frames.len();
+        let environment = self.environment.clone();
+        let id = self.new_exit_id();
+        self.runs.push(Run {
+            id,
+            top_level,
+            frames: base,
+            restarts: self.restarts.len(),
+        });
+        let result = loop {
+            let next = match step {
+                Step::Eval(e, env) => self.eval(&e, env),
+                Step::Return(value) => {
+                    if self.frames.len() == base {
+                        break Ok(value);
+                    }
+                    let frame = self.frames.pop().unwrap();
+                    self.resume(frame, value)
                 }
-                let mut value = true;
-                for v in args {
-                    match v.to_boolean() {
-                        Ok(b) => value = value && b,
-                        Err(s) => return Err(s),
+                Step::Call(p, args) => self.apply(p, args),
+            };
+            step = match next.or_else(|u| self.recover(base, u)) {
+                Ok(step) => step,
+                Err(u) => break Err(u),
+            };
+        };
+        self.runs.pop();
+        self.environment = environment;
+        result
+    }
+
+    /// Deals with what went wrong in a step. Errors are signalled right away, and anything
+    /// else unwinds the frames above `base` until one of them catches it.
+    fn recover(&mut self, base: usize, mut u: Unwind) -> Result<Step, Unwind> {
+        loop {
+            let result = match u {
+                Unwind::Error(message) => self.signal_error(Condition::new(message, Vec::new())),
+                Unwind::Signal(condition) => self.signal_error(condition),
+                Unwind::Reenter(k, values) => {
+                    if self.target_run(&k) != Some(self.runs.len() - 1) {
+                        return self.unwind(base, Unwind::Reenter(k, values));
                     }
+                    self.reenter(&k, values)
                 }
-                Ok(LValue::BooleanValue(value))
+                u => return self.unwind(base, u),
+            };
+            match result {
+                Ok(step) => return Ok(step),
+                Err(next) => u = next,
             }
-            Procedure::Or => {
-                if args.len() < 2 {
-                    return Err("'Or' requires at least two operands.".to_string());
+        }
+    }
+
+    /// Signals an error detected while evaluating an expression, with a `use-value` restart
+    /// that supplies a value for the expression instead.
+    fn signal_error(&mut self, mut condition: Condition) -> Result<Step, Unwind> {
+        if condition.location.is_none() {
+            condition.location = self.location();
+        }
+        let depth = self.restarts.len();
+        let id = self.push_restart("use-value", "Specify a value to use instead.", 1);
+        self.frames.push(Pending::UseValue { id, depth });
+        self.signal(LValue::Condition(Rc::new(condition)), false)
+    }
+
+    /// Pops the frames above `base` until one of them catches `u`, restoring the dynamic
+    /// state that each of them set up.
+    fn unwind(&mut self, base: usize, mut u: Unwind) -> Result<Step, Unwind> {
+        while self.frames.len() > base {
+            match self.frames.pop().unwrap() {
+                Pending::RestartCase { clauses, ids, depth, environment } => {
+                    self.restarts.truncate(depth);
+                    for (clause, id) in clauses.iter().zip(ids) {
+                        if let Some(values) = escape_values(&mut u, id) {
+                            let bindings = clause.arguments.iter().cloned().zip(values).collect();
+                            return self.sequence(&clause.body,
+                                                 Frame::new(bindings, Some(environment)));
+                        }
+                    }
+                }
+                Pending::UseValue { id, depth } => {
+                    self.restarts.truncate(depth);
+                    if let Some(mut values) = escape_values(&mut u, id) {
+                        return Ok(Step::Return(values.remove(0)));
+                    }
                 }
-                let mut value = false;
-                for v in args {
-                    match v.to_boolean() {
-                        Ok(b) => value = value || b,
-                        Err(s) => return Err(s),
+                Pending::Parameterized(depth) => self.parameterizations.truncate(depth),
+                Pending::Handler(handler) => self.handlers.push(handler),
+                Pending::WithHandler(depth) => self.handlers.truncate(depth),
+                Pending::Wind(wind) => {
+                    // The after thunk runs however the thunk is exited, unless a continuation
+                    // left the extent already. If it fails, its own unwind takes over.
+                    if !self.winds.last().is_some_and(|w| Rc::ptr_eq(w, &wind)) {
+                        continue;
+                    }
+                    self.winds.pop();
+                    if let Err(e) = self.evaluate_call(&wind.1, &[]) {
+                        u = e;
                     }
                 }
-                Ok(LValue::BooleanValue(value))
+                _ => {}
+            }
+        }
+        Err(u)
+    }
+
+    /// Captures the continuation of the current step, made of the frames of the current run.
+    fn capture(&self) -> Continuation {
+        let run = self.runs.last().unwrap();
+        Continuation {
+            run: run.id,
+            top_level: run.top_level,
+            frames: self.frames[run.frames..].to_vec(),
+            handlers: self.handlers.clone(),
+            restarts: self.restarts.clone(),
+            base_restarts: run.restarts,
+            winds: self.winds.clone(),
+            parameterizations: self.parameterizations.clone(),
+        }
+    }
+
+    /// Returns the index of the run that a continuation returns to: the one that captured it,
+    /// or the current top-level run if that was an earlier top-level run.
+    fn target_run(&self, k: &Continuation) -> Option<usize> {
+        match self.runs.iter().rposition(|r| r.id == k.run) {
+            Some(index) => Some(index),
+            None if k.top_level => self.runs.iter().rposition(|r| r.top_level),
+            None => None,
+        }
+    }
+
+    /// Passes values to a continuation that returns to the current run, after leaving the
+    /// `dynamic-wind` extents it isn't in and entering the ones it's in.
+    fn reenter(&mut self, k: &Continuation, values: Vec<LValue>) -> Result<Step, Unwind> {
+        self.rewind(&k.winds)?;
+        let run = self.runs.last().unwrap();
+        self.frames.truncate(run.frames);
+        self.frames.extend(k.frames.iter().cloned());
+        self.restarts.truncate(run.restarts);
+        self.restarts.extend(k.restarts[k.base_restarts..].iter().cloned());
+        self.handlers = k.handlers.clone();
+        self.parameterizations = k.parameterizations.clone();
+        Ok(Step::Return(LValue::from_values(values)))
+    }
+
+    /// Runs the after thunks of the extents that aren't in `winds`, innermost first, and then
+    /// the before thunks of the ones that are only in `winds`, outermost first.
+    fn rewind(&mut self, winds: &[Rc<(Procedure, Procedure)>]) -> Result<(), Unwind> {
+        let common = self.winds
+            .iter()
+            .zip(winds)
+            .take_while(|(a, b)| Rc::ptr_eq(a, b))
+            .count();
+        while self.winds.len() > common {
+            let wind = self.winds.pop().unwrap();
+            self.evaluate_call(&wind.1, &[])?;
+        }
+        for wind in &winds[common..] {
+            self.evaluate_call(&wind.0, &[])?;
+            self.winds.push(wind.clone());
+        }
+        Ok(())
+    }
+
+    /// Starts evaluating a body. Its last expression is in tail position.
+    fn sequence(&mut self, body: &Body, environment: Environment) -> Result<Step, Unwind> {
+        match body.len() {
+            0 => Err(Unwind::Error("Empty function body.".to_string())),
+            1 => Ok(Step::Eval(body[0].clone(), environment)),
+            _ => {
+                self.frames.push(Pending::Sequence {
+                    body: body.clone(),
+                    index: 0,
+                    environment: environment.clone(),
+                });
+                Ok(Step::Eval(body[0].clone(), environment))
             }
-            Procedure::Not => {
-                if args.len() != 1 {
-                    return Err("'Not' requires a single argument.".to_string());
+        }
+    }
+
+    /// Evaluates the initializer at `index` of a `let-values`, or its body once every
+    /// initializer has been bound.
+    fn let_values(&mut self,
+                  bindings: Rc<[(Formals, Rc<Expression>)]>,
+                  body: Body,
+                  sequential: bool,
+                  index: usize,
+                  frame: HashMap<String, LValue>,
+                  environment: Environment)
+                  -> Result<Step, Unwind> {
+        if index == bindings.len() {
+            return self.sequence(&body, Frame::new(frame, Some(environment)));
+        }
+        let init = bindings[index].1.clone();
+        self.frames.push(Pending::LetValues {
+            bindings,
+            body,
+            sequential,
+            index,
+            frame,
+            environment: environment.clone(),
+        });
+        Ok(Step::Eval(init, environment))
+    }
+
+    /// Evaluates the parameter of the first binding of a `parameterize` that has no value
+    /// yet, or its body once every binding has one.
+    fn parameterize(&mut self,
+                    bindings: Rc<[(Rc<Expression>, Rc<Expression>)]>,
+                    body: Body,
+                    values: Vec<(Rc<Parameter>, LValue)>,
+                    environment: Environment)
+                    -> Result<Step, Unwind> {
+        if values.len() == bindings.len() {
+            // The parameters are restored however the body is exited.
+            self.frames.push(Pending::Parameterized(self.parameterizations.len()));
+            self.parameterizations.extend(values);
+            return self.sequence(&body, environment);
+        }
+        let parameter = bindings[values.len()].0.clone();
+        self.frames.push(Pending::Parameterize {
+            bindings,
+            body,
+            parameter: None,
+            values,
+            environment: environment.clone(),
+        });
+        Ok(Step::Eval(parameter, environment))
+    }
+
+    fn eval(&mut self, e: &Expression, environment: Environment) -> Result<Step, Unwind> {
+        self.environment = environment.clone();
+        match *e {
+            Expression::Value(ref v) => Ok(Step::Return(v.clone())),
+            Expression::List(ref children) => {
+                if children.is_empty() {
+                    return Err(Unwind::Error("Can't evaluate an empty list.".to_string()));
                 }
-                match args[0].to_boolean() {
-                    Ok(b) => Ok(LValue::BooleanValue(!b)),
-                    Err(s) => return Err(s),
+                self.frames.push(Pending::Arguments {
+                    children: children.clone(),
+                    environment: environment.clone(),
+                    values: Vec::with_capacity(children.len()),
+                });
+                Ok(Step::Eval(children[0].clone(), environment))
+            }
+            Expression::Definition { ref name, ref value } => {
+                self.frames.push(Pending::Definition {
+                    name: name.clone(),
+                    environment: environment.clone(),
+                });
+                Ok(Step::Eval(value.clone(), environment))
+            }
+            Expression::Identifier(ref s) => {
+                match Frame::lookup(&environment, s) {
+                    Some(lres) => Ok(Step::Return(lres)),
+                    None => Err(Unwind::Error("Undefined identifier '".to_string() + s + "'.")),
                 }
             }
-            Procedure::UserDefined { ref arguments, ref body } => {
-                // Make sure that the arguments provided are enough.
-                if arguments.len() != args.len() {
-                    return Err("Invalid number of arguments provided.".to_string());
+            Expression::Lambda(ref lambda) => {
+                Ok(Step::Return(LValue::Procedure(Procedure::UserDefined {
+                    lambda: lambda.clone(),
+                    environment,
+                })))
+            }
+            Expression::IfCondition { ref cond, ref yes_expr, ref no_expr } => {
+                self.frames.push(Pending::IfCondition {
+                    yes_expr: yes_expr.clone(),
+                    no_expr: no_expr.clone(),
+                    environment: environment.clone(),
+                });
+                Ok(Step::Eval(cond.clone(), environment))
+            }
+            Expression::RestartCase { ref body, ref clauses } => {
+                let depth = self.restarts.len();
+                let mut ids = Vec::new();
+                for clause in clauses.iter().rev() {
+                    ids.push(self.push_restart(&clause.name,
+                                               &clause.description,
+                                               clause.arguments.len()));
                 }
-                // Create an argument map.
-                let mut arg_stack: HashMap<String, LValue> = HashMap::new();
-                for i in 0..args.len() {
-                    arg_stack.insert(arguments[i].clone(), args[i].clone());
+                ids.reverse();
+                self.frames.push(Pending::RestartCase {
+                    clauses: clauses.clone(),
+                    ids,
+                    depth,
+                    environment: environment.clone(),
+                });
+                Ok(Step::Eval(body.clone(), environment))
+            }
+            Expression::LetValues { ref bindings, ref body, sequential } => {
+                self.let_values(bindings.clone(),
+                                body.clone(),
+                                sequential,
+                                0,
+                                HashMap::new(),
+                                environment)
+            }
+            Expression::DefineValues { ref formals, ref value } => {
+                self.frames.push(Pending::DefineValues {
+                    formals: formals.clone(),
+                    environment: environment.clone(),
+                });
+                Ok(Step::Eval(value.clone(), environment))
+            }
+            Expression::Include(ref forms) if forms.is_empty() => {
+                Ok(Step::Return(LValue::Undefined))
+            }
+            Expression::Include(ref forms) => self.sequence(forms, environment),
+            Expression::DefineLibrary(ref definition) => {
+                self.define_library(definition)?;
+                Ok(Step::Return(LValue::Undefined))
+            }
+            Expression::CondExpand(ref clauses) => {
+                match clauses.iter().find(|c| self.requirement_holds(&c.0)) {
+                    Some((_, body)) if !body.is_empty() => self.sequence(body, environment),
+                    _ => Ok(Step::Return(LValue::Undefined)),
                 }
-                self.stack.push(arg_stack);
-                let mut lres: Result<LValue, String> = Err("Empty function body.".to_string());
-                for e in body {
-                    lres = self.evaluate_expression(e);
+            }
+            Expression::Import(ref sets) => {
+                for set in sets {
+                    let bindings = self.import(set)?;
+                    environment.borrow_mut().bindings.extend(bindings);
                 }
-                self.stack.pop();
-                lres
+                Ok(Step::Return(LValue::Undefined))
+            }
+            Expression::Parameterize { ref bindings, ref body } => {
+                self.parameterize(bindings.clone(), body.clone(), Vec::new(), environment)
+            }
+            Expression::Delay { ref body, delay_force } => {
+                let promise = Promise::new(PromiseState::Delayed {
+                    body: body.clone(),
+                    environment,
+                    delay_force,
+                });
+                Ok(Step::Return(LValue::Promise(promise)))
             }
         }
     }
 
-    fn evaluate_expression(&mut self, e: &Expression) -> Result<LValue, String> {
-        match *e {
-            Expression::Value(ref v) => Ok(v.clone()),
-            Expression::List(ref children) => {
-                if children.len() == 0 {
-                    return Err("Can't evaluate an empty list.".to_string());
-                }
-                let p: Procedure;
-                match self.evaluate_expression(&children[0]) {
-                    Ok(res) => {
-                        match res {
-                            LValue::Procedure(pval) => p = pval,
-                            _ => return Err("First element of list not a procedure.".to_string()),
-                        }
-                    }
-                    Err(s) => return Err(s),
+    /// Hands the value of an inner expression or call to the frame that was waiting for it.
+    fn resume(&mut self, frame: Pending, value: LValue) -> Result<Step, Unwind> {
+        match frame {
+            Pending::Arguments { children, environment, mut values } => {
+                if values.is_empty() && !matches!(value, LValue::Procedure(_)) {
+                    return Err(Unwind::Error("First element of list not a procedure."
+                        .to_string()));
+                }
+                values.push(value);
+                if values.len() < children.len() {
+                    let next = children[values.len()].clone();
+                    self.frames.push(Pending::Arguments {
+                        children,
+                        environment: environment.clone(),
+                        values,
+                    });
+                    return Ok(Step::Eval(next, environment));
                 }
-                let mut args: Vec<LValue> = Vec::new();
-                for arg in &children[1..] {
-                    match self.evaluate_expression(arg) {
-                        Ok(lres) => args.push(lres),
-                        Err(s) => return Err(s),
+                let args = values.split_off(1);
+                match values.pop() {
+                    Some(LValue::Procedure(p)) => {
+                        // Builtins like `the-environment` see the environment of the call.
+                        self.environment = environment;
+                        Ok(Step::Call(p, args))
                     }
+                    _ => unreachable!(),
                 }
-                self.evaluate_call(&p, &args)
             }
-            Expression::Eval(ref exp) => {
-                // Evaluate the argument.
-                match self.evaluate_expression(exp) {
-                    Ok(val) => {
-                        if let LValue::Quoted(e) = val {
-                            self.evaluate_expression(e.as_ref())
-                        } else {
-                            Ok(val)
-                        }
+            Pending::Sequence { body, index, environment } => {
+                let index = index + 1;
+                let next = body[index].clone();
+                if index + 1 < body.len() {
+                    self.frames.push(Pending::Sequence {
+                        body,
+                        index,
+                        environment: environment.clone(),
+                    });
+                }
+                Ok(Step::Eval(next, environment))
+            }
+            Pending::Definition { name, environment } => {
+                environment.borrow_mut().bindings.insert(name, value);
+                Ok(Step::Return(LValue::Undefined))
+            }
+            Pending::DefineValues { formals, environment } => {
+                let bindings = formals.bind(value.into_values()).map_err(Unwind::Error)?;
+                environment.borrow_mut().bindings.extend(bindings);
+                Ok(Step::Return(LValue::Undefined))
+            }
+            Pending::IfCondition { yes_expr, no_expr, environment } => {
+                if value.to_boolean().map_err(Unwind::Error)? {
+                    Ok(Step::Eval(yes_expr, environment))
+                } else {
+                    match no_expr {
+                        Some(no_expr) => Ok(Step::Eval(no_expr, environment)),
+                        None => Ok(Step::Return(LValue::Undefined)),
                     }
-                    Err(s) => Err(s),
                 }
             }
-            Expression::Definition { ref name, ref value } => {
-                let lres;
-                match self.evaluate_expression(value) {
-                    Ok(result) => lres = result.clone(),
-                    Err(s) => return Err(s),
-                }
-                let ref mut active_stack = *self.stack
-                    .last_mut()
-                    .unwrap();
-                active_stack.insert(name.to_string(), lres.clone());
-                Ok(LValue::Undefined)
+            Pending::RestartCase { depth, .. } | Pending::UseValue { depth, .. } => {
+                self.restarts.truncate(depth);
+                Ok(Step::Return(value))
             }
-            Expression::Identifier(ref s) => {
-                let result = self.find_identifier(s).cloned();
-                match result {
-                    Some(lres) => Ok(lres),
-                    None => Err("Undefined identifier '".to_string() + s + "'."),
+            Pending::LetValues { bindings,
+                                 body,
+                                 sequential,
+                                 index,
+                                 mut frame,
+                                 mut environment } => {
+                let values = value.into_values();
+                frame.extend(bindings[index].0.bind(values).map_err(Unwind::Error)?);
+                // Sequential bindings are visible to the initializers that follow them.
+                if sequential {
+                    environment = Frame::new(mem::take(&mut frame), Some(environment));
                 }
+                self.let_values(bindings, body, sequential, index + 1, frame, environment)
             }
-            Expression::Lambda(ref p) => Ok(LValue::Procedure(p.clone())),
-            Expression::IfCondition { ref cond, ref yes_expr, ref no_expr } => {
-                let result: bool;
-                match self.evaluate_expression(cond) {
-                    Ok(lres) => {
-                        match lres.to_boolean() {
-                            Ok(b) => {
-                                result = b;
-                            }
-                            Err(s) => return Err(s),
-                        }
+            Pending::Parameterize { bindings, body, parameter: None, values, environment } => {
+                let parameter = match value {
+                    LValue::Procedure(Procedure::Parameter(p)) => p,
+                    _ => {
+                        return Err(Unwind::Error("'parameterize' expects parameter objects."
+                            .to_string()))
                     }
-                    Err(s) => return Err(s),
+                };
+                let init = bindings[values.len()].1.clone();
+                self.frames.push(Pending::Parameterize {
+                    bindings,
+                    body,
+                    parameter: Some(parameter),
+                    values,
+                    environment: environment.clone(),
+                });
+                Ok(Step::Eval(init, environment))
+            }
+            Pending::Parameterize { bindings,
+                                    body,
+                                    parameter: Some(parameter),
+                                    mut values,
+                                    environment } => {
+                let value = self.convert_parameter(&parameter, value)?;
+                values.push((parameter, value));
+                self.parameterize(bindings, body, values, environment)
+            }
+            Pending::Parameterized(depth) => {
+                self.parameterizations.truncate(depth);
+                Ok(Step::Return(value))
+            }
+            Pending::Handler(handler) => {
+                self.handlers.push(handler);
+                Ok(Step::Return(value))
+            }
+            Pending::NonContinuable => {
+                let message = "Exception handler returned from a non-continuable raise.";
+                let condition = Condition::new(message.to_string(), Vec::new());
+                self.signal(LValue::Condition(Rc::new(condition)), false)
+            }
+            Pending::WithHandler(depth) => {
+                self.handlers.truncate(depth);
+                Ok(Step::Return(value))
+            }
+            Pending::WindBefore { before, thunk, after } => {
+                let wind = Rc::new((before, after));
+                self.winds.push(wind.clone());
+                self.frames.push(Pending::Wind(wind));
+                Ok(Step::Call(thunk, Vec::new()))
+            }
+            Pending::Wind(wind) => {
+                self.winds.pop();
+                self.frames.push(Pending::Value(value));
+                Ok(Step::Call(wind.1.clone(), Vec::new()))
+            }
+            Pending::Value(value) => Ok(Step::Return(value)),
+            Pending::Consumer(consumer) => Ok(Step::Call(consumer, value.into_values())),
+            Pending::Force(promise, delay_force) => {
+                // Forcing the body may have forced this promise already.
+                if let PromiseState::Forced(_) = promise.state() {
+                    return self.force(&promise);
+                }
+                if !delay_force {
+                    promise.set_state(PromiseState::Forced(value.clone()));
+                    Ok(Step::Return(value))
+                } else if let LValue::Promise(ref next) = value {
+                    promise.adopt(next);
+                    self.force(&promise)
+                } else {
+                    Err(Unwind::Error("The body of 'delay-force' must evaluate to a promise."
+                        .to_string()))
+                }
+            }
+        }
+    }
+
+    /// Calls a procedure. User-defined procedures and the builtins that take part in the
+    /// control flow are evaluated on the frames, and the other builtins right away.
+    fn apply(&mut self, p: Procedure, args: Vec<LValue>) -> Result<Step, Unwind> {
+        if let Procedure::UserDefined { ref lambda, ref environment } = p {
+            // Create an argument map, making sure that the arguments provided are enough.
+            let arg_stack = lambda.arguments.bind(args).map_err(Unwind::Error)?;
+            return self.sequence(&lambda.body, Frame::new(arg_stack, Some(environment.clone())));
+        }
+        let (min, max) = p.arity();
+        if args.len() < min || matches!(max, Some(max) if args.len() > max) {
+            return Err(arity_error(&p, args.len()));
+        }
+        let p = &p;
+        let args = &args[..];
+        match *p {
+            Procedure::WithExceptionHandler => {
+                let handler = procedure_argument(p, args, 0)?;
+                let thunk = procedure_argument(p, args, 1)?;
+                self.frames.push(Pending::WithHandler(self.handlers.len()));
+                self.handlers.push(handler);
+                Ok(Step::Call(thunk, Vec::new()))
+            }
+            Procedure::Raise | Procedure::RaiseContinuable => {
+                let continuable = matches!(*p, Procedure::RaiseContinuable);
+                self.signal(args[0].clone(), continuable)
+            }
+            Procedure::Error => {
+                let message = string_argument(p, args, 0)?.borrow().clone();
+                let mut condition = Condition::new(message, args[1..].to_vec());
+                condition.location = self.location();
+                self.signal(LValue::Condition(Rc::new(condition)), false)
+            }
+            Procedure::DynamicWind => {
+                let before = procedure_argument(p, args, 0)?;
+                let thunk = procedure_argument(p, args, 1)?;
+                let after = procedure_argument(p, args, 2)?;
+                // The after thunk runs however the thunk is exited: by returning, by an error
+                // propagating out of it or by an escape to a restart or continuation.
+                self.frames.push(Pending::WindBefore {
+                    before: before.clone(),
+                    thunk,
+                    after,
+                });
+                Ok(Step::Call(before, Vec::new()))
+            }
+            Procedure::CallWithCurrentContinuation => {
+                let receiver = procedure_argument(p, args, 0)?;
+                let k = Procedure::Continuation(Rc::new(self.capture()));
+                Ok(Step::Call(receiver, vec![LValue::Procedure(k)]))
+            }
+            Procedure::Continuation(ref k) => {
+                // The frames of a builtin's call back into Scheme code are gone once it has
+                // returned, since the builtin's own part of the call can't be copied.
+                if self.target_run(k).is_none() {
+                    return Err(Unwind::Error("Can't re-enter a continuation captured in a call \
+                                              from a builtin that has returned."
+                        .to_string()));
                 }
-                if result == true {
-                    self.evaluate_expression(yes_expr)
+                Err(Unwind::Reenter(k.clone(), args.to_vec()))
+            }
+            Procedure::CallWithValues => {
+                let producer = procedure_argument(p, args, 0)?;
+                let consumer = procedure_argument(p, args, 1)?;
+                self.frames.push(Pending::Consumer(consumer));
+                Ok(Step::Call(producer, Vec::new()))
+            }
+            Procedure::Force => {
+                match args[0] {
+                    LValue::Promise(ref promise) => self.force(promise),
+                    ref value => Ok(Step::Return(value.clone())),
+                }
+            }
+            Procedure::Eval => {
+                let environment = if args.len() == 2 {
+                    environment_argument(p, args, 1)?
                 } else {
-                    match *no_expr {
-                        Some(ref no_e) => self.evaluate_expression(no_e),
-                        None => Ok(LValue::Undefined),
+                    self.environment.clone()
+                };
+                match args[0] {
+                    LValue::Symbol(_) | LValue::Pair(_) | LValue::Nil => {
+                        let directory = self.source_directory();
+                        let e = args[0]
+                            .to_list()
+                            .and_then(|l| Expression::from_list(&l, &directory))
+                            .map_err(Unwind::Error)?;
+                        Ok(Step::Eval(Rc::new(e), environment))
                     }
+                    ref value => Ok(Step::Return(value.clone())),
                 }
             }
+            _ => Ok(Step::Return(self.evaluate_builtin(p, args)?)),
         }
     }
 }
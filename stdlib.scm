@@ -2,11 +2,104 @@
 
 (define pi 3.14159)
 
-(define <= (lambda (a b)
-  (or (< a b) (= a b))))
-
-(define >= (lambda (a b)
-  (or (> a b) (= a b))))
-
 (define abs (lambda (x)
   (if (< x 0) (- x) x)))
+
+; Streams (SRFI 41). A stream is a promise of either the empty list or a pair of a promise
+; for its first element and the stream of the remaining ones.
+
+(define stream-null (delay '()))
+
+(define stream? (lambda (s)
+  (promise? s)))
+
+(define stream-null? (lambda (s)
+  (if (promise? s) (null? (force s)) #f)))
+
+(define stream-pair? (lambda (s)
+  (if (promise? s) (pair? (force s)) #f)))
+
+(define stream-car (lambda (s)
+  (if (stream-pair? s)
+      (force (car (force s)))
+      (error "stream-car: not a stream pair:" s))))
+
+(define stream-cdr (lambda (s)
+  (if (stream-pair? s)
+      (cdr (force s))
+      (error "stream-cdr: not a stream pair:" s))))
+
+(define list->stream (lambda (l)
+  (if (null? l)
+      stream-null
+      (stream-cons (car l) (list->stream (cdr l))))))
+
+(define stream (lambda items
+  (list->stream items)))
+
+; (stream->list [n] s) collects the first n elements of s, or all of them.
+(define stream->list (lambda args
+  (if (null? (cdr args))
+      (%stream->list #f (car args))
+      (%stream->list (car args) (car (cdr args))))))
+
+(define %stream->list (lambda (n s)
+  (if (if (stream-null? s) #t (if n (= n 0) #f))
+      '()
+      (cons (stream-car s) (%stream->list (if n (- n 1) n) (stream-cdr s))))))
+
+(define stream-take (lambda (n s)
+  (delay-force
+    (if (if (= n 0) #t (stream-null? s))
+        stream-null
+        (stream-cons (stream-car s) (stream-take (- n 1) (stream-cdr s)))))))
+
+(define stream-drop (lambda (n s)
+  (delay-force
+    (if (if (= n 0) #t (stream-null? s))
+        s
+        (stream-drop (- n 1) (stream-cdr s))))))
+
+(define stream-ref (lambda (s n)
+  (stream-car (stream-drop n s))))
+
+(define stream-map (lambda (f s)
+  (delay-force
+    (if (stream-null? s)
+        stream-null
+        (stream-cons (f (stream-car s)) (stream-map f (stream-cdr s)))))))
+
+(define stream-filter (lambda (pred s)
+  (delay-force
+    (if (stream-null? s)
+        stream-null
+        (if (pred (stream-car s))
+            (stream-cons (stream-car s) (stream-filter pred (stream-cdr s)))
+            (stream-filter pred (stream-cdr s)))))))
+
+(define stream-for-each (lambda (f s)
+  (if (stream-pair? s)
+      ((lambda ()
+         (f (stream-car s))
+         (stream-for-each f (stream-cdr s)))))))
+
+(define stream-append (lambda streams
+  (if (null? streams)
+      stream-null
+      (%stream-append (car streams) (cdr streams)))))
+
+(define %stream-append (lambda (s rest)
+  (delay-force
+    (if (stream-null? s)
+        (if (null? rest) stream-null (%stream-append (car rest) (cdr rest)))
+        (stream-cons (stream-car s) (%stream-append (stream-cdr s) rest))))))
+
+; (stream-from first [step]) is the infinite stream first, first + step, ...
+(define stream-from (lambda (first . step)
+  (%stream-from first (if (null? step) 1 (car step)))))
+
+(define %stream-from (lambda (first step)
+  (stream-cons first (%stream-from (+ first step) step))))
+
+(define stream-iterate (lambda (f base)
+  (stream-cons base (stream-iterate f (f base)))))
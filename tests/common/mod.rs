@@ -0,0 +1,16 @@
+use std::env;
+use std::fs;
+use std::process::{self, Command, Stdio};
+
+/// Runs `code` as a script file, and returns what the interpreter printed.
+pub fn run(name: &str, code: &str) -> String {
+    let path = env::temp_dir().join(format!("rscheme-{}-{}.scm", name, process::id()));
+    fs::write(&path, code).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rscheme"))
+        .arg(&path)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    String::from_utf8(output.stdout).unwrap()
+}
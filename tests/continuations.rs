@@ -0,0 +1,27 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn continuations_can_be_reentered() {
+    let output = run("reenter",
+                     "(define k (vector #f))\n\
+                      (define n (vector 0))\n\
+                      (display (+ 100 (call/cc (lambda (c) (vector-set! k 0 c) 0))))\n\
+                      (vector-set! n 0 (+ (vector-ref n 0) 1))\n\
+                      (if (< (vector-ref n 0) 2) ((vector-ref k 0) 1) 'done)\n");
+    assert!(output.contains("100101"));
+}
+
+/// A builtin's own part of a call back into Scheme code isn't kept, so a continuation
+/// captured in the callback can't be re-entered once the builtin has returned.
+#[test]
+fn continuations_from_finished_builtin_callbacks_are_refused() {
+    let output = run("reenter-builtin",
+                     "(define k (vector #f))\n\
+                      (vector-for-each (lambda (x) (call/cc (lambda (c) (vector-set! k 0 c))))\n\
+                                       (vector 1))\n\
+                      ((vector-ref k 0) 0)\n");
+    assert!(output.contains("Can't re-enter a continuation captured in a call from a builtin \
+                             that has returned."));
+}
@@ -0,0 +1,26 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn eval_keeps_to_its_environment() {
+    let output = run("eval-environment",
+                     "(define e (environment '(only (scheme base) +)))\n\
+                      (display (eval '(+ 1 2) e))\n\
+                      (eval '(car '(1)) e)\n");
+    assert!(output.contains("3"));
+    assert!(output.contains("Undefined identifier 'car'."));
+}
+
+#[test]
+fn eval_cannot_import_into_its_environment() {
+    let output = run("eval-import",
+                     "(define e (environment '(only (scheme base) +)))\n\
+                      (eval '(import (scheme base)) e)\n\
+                      (eval '((lambda () (import (scheme base)))) e)\n\
+                      (eval '(define-library (escape) (import (scheme base))) e)\n\
+                      (eval '(car '(1)) e)\n");
+    let rejected = output.matches("is only allowed at the top level of a program or library.");
+    assert_eq!(rejected.count(), 3);
+    assert!(output.contains("Undefined identifier 'car'."));
+}